@@ -0,0 +1,344 @@
+#![allow(dead_code)]
+use super::natural::{parse_natural, Locale};
+use super::task_model::parse_task_date;
+use super::TaskError;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{
+    DateTime, Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+/// Format dates in `select` filters are resolved to, in UTC. [parse_task_date] always accepts it.
+///
+/// It keeps the seconds, dates are compared down to them
+pub const CANONICAL_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Absolute formats accepted out of the box, the date-only ones get the default time of day
+pub const DEFAULT_INPUT_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%d.%m.%Y %H:%M",
+    "%Y-%m-%d",
+    "%d.%m.%Y",
+];
+
+/// How dates are printed back unless configured otherwise
+pub const DEFAULT_DISPLAY_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Source of the current time relative dates are resolved against
+pub trait Clock: Debug {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Always returns the same time, keeps tests deterministic
+#[derive(Debug)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// Turns the dates typed into `add`, `update` and `select` into UTC timestamps
+/// and prints them back in the configured display format and time zone.
+///
+/// Dates without an offset are wall clock times in the configured zone, UTC by default,
+/// or in the zone named after them: `2024-05-01 09:00 Europe/Berlin`.
+/// RFC 3339 timestamps like `2024-05-01T09:00:00+02:00` carry their own offset.
+///
+/// Absolute dates are accepted in any of the input formats, [DEFAULT_INPUT_FORMATS] by default.
+/// A format without a time of day takes the configured default time, midnight unless changed.
+///
+/// It also understands relative dates made of
+/// an optional anchor, any number of offsets and an optional time of day:
+///
+/// - anchors: `now` (the default), `today`, `tomorrow`, `yesterday`,
+///   `start_of_week`, `end_of_week`, `start_of_month`, `end_of_month`
+/// - offsets: `+30min`, `-1h`, `+3d`, `-2w`, `+1mo`, `+1y`
+/// - time of day: `09:30`
+///
+/// e.g. `tomorrow 09:00`, `+3d`, `start_of_week +1w`.
+/// Weeks start on Monday, the anchors other than `now` are at midnight.
+///
+/// Anything else is read as a phrase in the configured [Locale], see [parse_natural].
+#[derive(Debug, Clone)]
+pub struct DateParser {
+    clock: Rc<dyn Clock>,
+    locale: Locale,
+    input_formats: Vec<String>,
+    default_time: NaiveTime,
+    display_format: String,
+    zone: Tz,
+}
+
+impl Default for DateParser {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl DateParser {
+    pub fn new(clock: impl Clock + 'static) -> Self {
+        Self {
+            clock: Rc::new(clock),
+            locale: Locale::default(),
+            input_formats: DEFAULT_INPUT_FORMATS
+                .iter()
+                .map(|format| format.to_string())
+                .collect(),
+            default_time: NaiveTime::MIN,
+            display_format: DEFAULT_DISPLAY_FORMAT.to_string(),
+            zone: Tz::UTC,
+        }
+    }
+
+    /// Zone dates are entered and shown in unless a task has its own
+    pub fn with_zone(mut self, zone: Tz) -> Self {
+        self.zone = zone;
+        self
+    }
+
+    pub fn zone(&self) -> Tz {
+        self.zone
+    }
+
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    pub fn locale(&self) -> Locale {
+        self.locale
+    }
+
+    /// Replaces the accepted absolute formats, `%Y-%m-%d %H:%M` is always accepted on top of them
+    pub fn with_input_formats<I, F>(mut self, formats: I) -> Result<Self, TaskError>
+    where
+        I: IntoIterator<Item = F>,
+        F: Into<String>,
+    {
+        let formats: Vec<String> = formats.into_iter().map(Into::into).collect();
+        for format in &formats {
+            validate_format(format)?;
+        }
+
+        self.input_formats = formats;
+        Ok(self)
+    }
+
+    /// Time of day given to dates entered without one
+    pub fn with_default_time(mut self, time: NaiveTime) -> Self {
+        self.default_time = time;
+        self
+    }
+
+    pub fn with_display_format(mut self, format: &str) -> Result<Self, TaskError> {
+        validate_format(format)?;
+        self.display_format = format.to_string();
+        Ok(self)
+    }
+
+    pub fn display_format(&self) -> &str {
+        &self.display_format
+    }
+
+    /// Prints `date` in the display format, in `zone` or the configured zone without one
+    pub fn format(&self, date: DateTime<Utc>, zone: Option<Tz>) -> String {
+        date.with_timezone(&zone.unwrap_or(self.zone))
+            .format(&self.display_format)
+            .to_string()
+    }
+
+    /// Current wall clock time in the configured zone
+    pub fn now(&self) -> NaiveDateTime {
+        self.clock.now().with_timezone(&self.zone).naive_local()
+    }
+
+    /// Wall clock time `date` stands for in the configured zone
+    pub fn parse(&self, date: &str) -> Result<NaiveDateTime, TaskError> {
+        self.parse_wall(date.trim(), self.zone)
+    }
+
+    /// Resolves `date` to a timestamp along with the zone it was entered in.
+    ///
+    /// A zone named in `date` wins over `zone`, without either the configured zone is used
+    /// and the returned zone is [None].
+    pub fn resolve(
+        &self,
+        date: &str,
+        zone: Option<Tz>,
+    ) -> Result<(DateTime<Utc>, Option<Tz>), TaskError> {
+        let date = date.trim();
+        if let Ok(parsed) = DateTime::parse_from_rfc3339(date) {
+            return Ok((parsed.with_timezone(&Utc), zone));
+        }
+
+        let (date, zone) = match date.rsplit_once(' ') {
+            Some((rest, name)) if name.contains('/') || name.eq_ignore_ascii_case("utc") => {
+                let named = name
+                    .parse::<Tz>()
+                    .or_else(|_| name.to_uppercase().parse::<Tz>())
+                    .map_err(|_| TaskError::TaskZoneParseError(name.to_string()))?;
+                (rest.trim(), Some(named))
+            }
+            _ => (date, zone),
+        };
+
+        let in_zone = zone.unwrap_or(self.zone);
+        let wall = self.parse_wall(date, in_zone)?;
+        Ok((localize(wall, in_zone)?, zone))
+    }
+
+    /// Resolves `date` and writes it back in the [CANONICAL_FORMAT], in UTC.
+    /// Fractions of a second are dropped
+    pub fn canonicalize(&self, date: &str) -> Result<String, TaskError> {
+        let (resolved, _) = self.resolve(date, None)?;
+        Ok(resolved.naive_utc().format(CANONICAL_FORMAT).to_string())
+    }
+
+    fn parse_wall(&self, date: &str, zone: Tz) -> Result<NaiveDateTime, TaskError> {
+        if let Ok(parsed) = parse_task_date(date.to_string()) {
+            return Ok(parsed);
+        }
+
+        let now = self.clock.now().with_timezone(&zone).naive_local();
+        self.parse_absolute(date)
+            .or_else(|| parse_relative(date, now))
+            .or_else(|| parse_natural(date, now, self.locale, self.default_time))
+            .ok_or_else(|| TaskError::TaskDateParseError(date.to_string()))
+    }
+
+    fn parse_absolute(&self, date: &str) -> Option<NaiveDateTime> {
+        self.input_formats.iter().find_map(|format| {
+            NaiveDateTime::parse_from_str(date, format)
+                .or_else(|_| {
+                    NaiveDate::parse_from_str(date, format)
+                        .map(|day| day.and_time(self.default_time))
+                })
+                .ok()
+        })
+    }
+}
+
+/// Resolves an anchor, offsets and time of day against `now`
+fn parse_relative(date: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let mut words = date.split_whitespace().peekable();
+    words.peek()?;
+
+    let mut resolved = match words.peek().and_then(|word| anchor(now, word)) {
+        Some(anchored) => {
+            words.next();
+            anchored?
+        }
+        None => now,
+    };
+
+    for word in words {
+        resolved = match NaiveTime::parse_from_str(word, "%H:%M") {
+            Ok(time) => resolved.date().and_time(time),
+            Err(_) => apply_offset(resolved, word)?,
+        };
+    }
+
+    Some(resolved)
+}
+
+/// Resolves a named anchor, [None] when `word` is not one
+fn anchor(now: NaiveDateTime, word: &str) -> Option<Option<NaiveDateTime>> {
+    let today = now.date().and_time(NaiveTime::MIN);
+    let from_monday = Days::new(today.weekday().num_days_from_monday() as u64);
+
+    let anchored = match word.to_lowercase().as_str() {
+        "now" => Some(now),
+        "today" => Some(today),
+        "tomorrow" => today.checked_add_days(Days::new(1)),
+        "yesterday" => today.checked_sub_days(Days::new(1)),
+        "start_of_week" => today.checked_sub_days(from_monday),
+        "end_of_week" => today
+            .checked_sub_days(from_monday)
+            .and_then(|monday| monday.checked_add_days(Days::new(6))),
+        "start_of_month" => today.with_day(1),
+        "end_of_month" => today
+            .with_day(1)
+            .and_then(|first| first.checked_add_months(Months::new(1)))
+            .and_then(|next| next.checked_sub_days(Days::new(1))),
+        _ => return None,
+    };
+
+    Some(anchored)
+}
+
+/// Applies a signed offset such as `+3d` or `-1mo`
+fn apply_offset(date: NaiveDateTime, offset: &str) -> Option<NaiveDateTime> {
+    let (forward, offset) = match offset.as_bytes().first()? {
+        b'+' => (true, &offset[1..]),
+        b'-' => (false, &offset[1..]),
+        _ => return None,
+    };
+
+    let unit_at = offset.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = offset.split_at(unit_at);
+    let amount: u32 = amount.parse().ok()?;
+
+    let months = match unit.to_lowercase().as_str() {
+        "mo" => Some(amount),
+        "y" => amount.checked_mul(12),
+        _ => None,
+    };
+    if let Some(months) = months {
+        let months = Months::new(months);
+        return match forward {
+            true => date.checked_add_months(months),
+            false => date.checked_sub_months(months),
+        };
+    }
+
+    let duration = match unit.to_lowercase().as_str() {
+        "min" => Duration::minutes(amount as i64),
+        "h" => Duration::hours(amount as i64),
+        "d" => Duration::days(amount as i64),
+        "w" => Duration::weeks(amount as i64),
+        _ => return None,
+    };
+    match forward {
+        true => date.checked_add_signed(duration),
+        false => date.checked_sub_signed(duration),
+    }
+}
+
+/// A typo like `%Q` would only surface as a panic once a date is printed
+fn validate_format(format: &str) -> Result<(), TaskError> {
+    if format.is_empty() || StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(TaskError::DateFormatError(format.to_string()));
+    }
+    Ok(())
+}
+
+/// Timestamp of the wall clock time `date` in `zone`.
+///
+/// A time repeated when clocks go back is taken the first time it happens,
+/// a time skipped when they go forward is moved past the gap.
+pub fn localize(date: NaiveDateTime, zone: Tz) -> Result<DateTime<Utc>, TaskError> {
+    zone.from_local_datetime(&date)
+        .earliest()
+        .or_else(|| {
+            zone.from_local_datetime(&(date + Duration::hours(1)))
+                .earliest()
+        })
+        .map(|date| date.with_timezone(&Utc))
+        .ok_or_else(|| TaskError::TaskDateParseError(date.to_string()))
+}
@@ -0,0 +1,150 @@
+#![allow(dead_code)]
+use super::task_model::Status;
+use super::Task;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Tasks along with what they wait on, see [Task::blocked_by].
+///
+/// Only tasks that are neither done nor cancelled hold anything up,
+/// ids of tasks that are gone are ignored.
+#[derive(Debug, Clone)]
+pub struct Dependencies {
+    tasks: BTreeMap<u64, Task>,
+}
+
+impl Dependencies {
+    pub fn new(tasks: Vec<Task>) -> Self {
+        Self {
+            tasks: tasks.into_iter().map(|task| (task.id, task)).collect(),
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Task> {
+        self.tasks.get(&id)
+    }
+
+    /// The tasks `task` waits on that are still open
+    pub fn open_blockers(&self, task: &Task) -> Vec<u64> {
+        task.blocked_by
+            .iter()
+            .copied()
+            .filter(|id| self.tasks.get(id).is_some_and(is_open))
+            .collect()
+    }
+
+    /// Fills in [Task::is_blocked] of the tasks
+    pub fn mark_blocked(&self, tasks: &mut [Task]) {
+        for task in tasks {
+            task.is_blocked = !self.open_blockers(task).is_empty();
+        }
+    }
+
+    /// The tasks that would end up waiting on each other if the task with id `task` waited on
+    /// `blocked_by`, as in [TaskMgrError::DependencyCycleError](crate::back::TaskMgrError).
+    /// [None] when they wouldn't.
+    pub fn cycle(&self, task: u64, blocked_by: &BTreeSet<u64>) -> Option<Vec<u64>> {
+        let mut seen = BTreeSet::new();
+        for &blocker in blocked_by {
+            let mut path = vec![task];
+            if self.path_to(blocker, task, &mut seen, &mut path) {
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    /// Depth first search from `from` along [Task::blocked_by], pushing the way to `to` to `path`
+    fn path_to(&self, from: u64, to: u64, seen: &mut BTreeSet<u64>, path: &mut Vec<u64>) -> bool {
+        path.push(from);
+        if from == to {
+            return true;
+        }
+        if seen.insert(from) {
+            if let Some(task) = self.tasks.get(&from) {
+                for &next in &task.blocked_by {
+                    if self.path_to(next, to, seen, path) {
+                        return true;
+                    }
+                }
+            }
+        }
+        path.pop();
+        false
+    }
+
+    /// The open tasks in an order that has each of them after the ones it waits on.
+    ///
+    /// Of the tasks that are free to go at the same time the most urgent comes first,
+    /// then the one added first. Fails with the tasks that wait on each other if there are any.
+    pub fn execution_order(&self) -> Result<Vec<&Task>, Vec<u64>> {
+        let open: Vec<&Task> = self.tasks.values().filter(|task| is_open(task)).collect();
+
+        // how many open tasks each one still waits on, and who waits on each one
+        let mut waiting: BTreeMap<u64, usize> = BTreeMap::new();
+        let mut dependents: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+        for task in &open {
+            let blockers = self.open_blockers(task);
+            waiting.insert(task.id, blockers.len());
+            for blocker in blockers {
+                dependents.entry(blocker).or_default().push(task.id);
+            }
+        }
+
+        let mut order = Vec::new();
+        let mut ready: Vec<&Task> = open
+            .iter()
+            .copied()
+            .filter(|task| waiting[&task.id] == 0)
+            .collect();
+        while !ready.is_empty() {
+            // the last one goes next, so most urgent last and the earliest added among them
+            ready.sort_by(|one, other| {
+                one.priority
+                    .cmp(&other.priority)
+                    .then(other.id.cmp(&one.id))
+            });
+            let task = ready.pop().unwrap();
+            order.push(task);
+
+            for dependent in dependents.get(&task.id).into_iter().flatten() {
+                let left = waiting.get_mut(dependent).unwrap();
+                *left -= 1;
+                if *left == 0 {
+                    ready.push(&self.tasks[dependent]);
+                }
+            }
+        }
+
+        if order.len() < open.len() {
+            let stuck = open
+                .iter()
+                .find(|task| !order.iter().any(|done| done.id == task.id))
+                .unwrap();
+            return Err(self.stuck_cycle(stuck.id, &order));
+        }
+        Ok(order)
+    }
+
+    /// Follows the open blockers from a task left out of the order until they come around
+    fn stuck_cycle(&self, from: u64, ordered: &[&Task]) -> Vec<u64> {
+        let mut path = vec![from];
+        loop {
+            let task = &self.tasks[path.last().unwrap()];
+            let next = self
+                .open_blockers(task)
+                .into_iter()
+                .find(|id| !ordered.iter().any(|task| task.id == *id))
+                .unwrap();
+            if let Some(start) = path.iter().position(|id| *id == next) {
+                let mut cycle = path.split_off(start);
+                cycle.push(next);
+                return cycle;
+            }
+            path.push(next);
+        }
+    }
+}
+
+fn is_open(task: &Task) -> bool {
+    !matches!(task.status, Status::Done | Status::Cancelled)
+}
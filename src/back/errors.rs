@@ -1,28 +1,315 @@
 #![allow(dead_code)]
+use super::expression::{did_you_mean, LeftVar};
+use super::task_model::{Priority, Status};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::ops::Range;
+
 #[derive(Debug, Clone)]
 pub enum TaskMgrError {
+    /// Input that is not a valid date, zone, format or the like, shown along with the input
     GeneralTaskError(TaskError),
     TaskCreationErrorProp(TaskError),
-    TaskNotFound,
-    TaskUpdateError,
+    /// Nothing matches the task reference, holds the reference
+    TaskNotFound(String),
+    /// The name matches several tasks, holds the name and their ids
+    AmbiguousTaskName(String, Vec<u64>),
+    /// There is no task to update, holds the task reference
+    TaskUpdateError(String),
     TaskUpdateErrorPropTask(TaskError),
-    TaskDeleteError,
-    WrongQuery,
-    WrongQueryPropExpr(ExpressionError),
-    WrongCommand,
+    /// The task would end up among its own subtasks, holds the task and the parent it was given
+    SubtaskCycleError(u64, u64),
+    /// Only checked with strict subtasks, holds the task reference and how many are still open
+    OpenSubtasksError(String, usize),
+    /// The tasks would wait on each other, holds them in the order they would wait,
+    /// the first one repeated at the end
+    DependencyCycleError(Vec<u64>),
+    /// The task can't be started or done yet, holds the task reference and the open tasks it waits on
+    WaitingOnError(String, Vec<u64>),
+    NothingToUndo,
+    NothingToRedo,
+    /// The command or query can't be run as written, holds it
+    WrongQuery(String),
+    WrongQueryPropExpr(QueryError),
+    /// Holds the unknown command
+    WrongCommand(String),
+    /// The input holds nothing but whitespace
+    EmptyCommand,
+    StorageErrorProp(StorageError),
+}
+
+impl Display for TaskMgrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskMgrError::GeneralTaskError(e) => write!(f, "invalid input: {}", e),
+            TaskMgrError::TaskCreationErrorProp(_) => write!(f, "could not add the task"),
+            TaskMgrError::TaskNotFound(task_ref) => write!(f, "no task matches '{}'", task_ref),
+            TaskMgrError::AmbiguousTaskName(name, found) => write!(
+                f,
+                "several tasks are named '{}': {}, pick one by its id",
+                name,
+                ids(found, ", ")
+            ),
+            TaskMgrError::TaskUpdateError(task_ref) => {
+                write!(f, "could not update '{}', no task matches it", task_ref)
+            }
+            TaskMgrError::TaskUpdateErrorPropTask(_) => write!(f, "could not update the task"),
+            TaskMgrError::SubtaskCycleError(task, parent) if task == parent => {
+                write!(f, "#{} cannot be its own parent", task)
+            }
+            TaskMgrError::SubtaskCycleError(task, parent) => write!(
+                f,
+                "#{} is a subtask of #{}, it cannot be its parent",
+                parent, task
+            ),
+            TaskMgrError::OpenSubtasksError(task_ref, open) => write!(
+                f,
+                "'{}' still has {} open subtask(s), finish or cancel them first",
+                task_ref, open
+            ),
+            TaskMgrError::DependencyCycleError(cycle) => {
+                write!(
+                    f,
+                    "the tasks would wait on each other: {}",
+                    ids(cycle, " -> ")
+                )
+            }
+            TaskMgrError::WaitingOnError(task_ref, blockers) => write!(
+                f,
+                "'{}' is waiting on {}, finish or cancel them first",
+                task_ref,
+                ids(blockers, ", ")
+            ),
+            TaskMgrError::NothingToUndo => write!(f, "nothing to undo"),
+            TaskMgrError::NothingToRedo => write!(f, "nothing to redo"),
+            TaskMgrError::WrongQuery(query) => write!(f, "malformed command '{}'", query),
+            TaskMgrError::WrongQueryPropExpr(_) => write!(f, "invalid query"),
+            TaskMgrError::WrongCommand(command) => write!(f, "unknown command '{}'", command),
+            TaskMgrError::EmptyCommand => write!(f, "no command given"),
+            TaskMgrError::StorageErrorProp(_) => write!(f, "could not access the tasks"),
+        }
+    }
+}
+
+/// `#1, #2` for `separator` `", "`
+fn ids(ids: &[u64], separator: &str) -> String {
+    let ids: Vec<String> = ids.iter().map(|id| format!("#{}", id)).collect();
+    ids.join(separator)
+}
+
+impl Error for TaskMgrError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            // GeneralTaskError names its cause in the message already
+            TaskMgrError::TaskCreationErrorProp(e) | TaskMgrError::TaskUpdateErrorPropTask(e) => {
+                Some(e)
+            }
+            TaskMgrError::WrongQueryPropExpr(e) => Some(e),
+            TaskMgrError::StorageErrorProp(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum TaskError {
-    TaskDateParseError,
-    TaskUpdateError,
-    TaskCreationArgsError,
+    /// Holds the text that is not a date
+    TaskDateParseError(String),
+    /// Fewer fields than a task needs, holds how many were given
+    TaskCreationArgsError(usize),
+    /// The configured date format has an unknown specifier, holds the format
+    DateFormatError(String),
+    /// Not an IANA time zone name, holds the name
+    TaskZoneParseError(String),
+    /// Unknown or malformed `key=value` task option, holds the option
+    TaskOptionError(String),
+    /// Malformed `repeat=` rule, holds the rule
+    RecurrenceParseError(String),
+    /// The task cannot go from the first status to the second
+    IllegalTransitionError(Status, Status),
 }
 
+impl Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::TaskDateParseError(date) => write!(f, "'{}' is not a date", date),
+            TaskError::TaskCreationArgsError(given) => write!(
+                f,
+                "expected name; description; date; category, got {} field(s)",
+                given
+            ),
+            TaskError::DateFormatError(format) => {
+                write!(f, "'{}' is not a valid date format", format)
+            }
+            TaskError::TaskZoneParseError(zone) => {
+                write!(f, "'{}' is not an IANA time zone name", zone)
+            }
+            TaskError::TaskOptionError(option) => write!(f, "unknown task option '{}'", option),
+            TaskError::RecurrenceParseError(rule) => {
+                write!(f, "'{}' is not a valid repeat rule", rule)
+            }
+            TaskError::IllegalTransitionError(from, to) if from == to => {
+                write!(f, "the task is already {}", to)
+            }
+            TaskError::IllegalTransitionError(from, to) => {
+                write!(f, "a {} task cannot become {}", from, to)
+            }
+        }
+    }
+}
+
+impl Error for TaskError {}
+
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum ExpressionError {
     LikeParseError,
     ExprParseError,
     ArgParseError,
     OpParseError,
+    UnexpectedEndError,
+    UnbalancedParensError,
+    UnterminatedStringError,
+    NumberParseError,
+    /// Not a [Task](crate::back::Task) field, holds the name
+    UnknownFieldError(String),
+    /// `select` lists a column [Task](crate::back::Task) doesn't have
+    UnknownColumnError(String),
+    /// `group by` is only meaningful for `select count(*)`
+    GroupByWithoutCountError,
+    /// Tags only take `has`, `any` and `all`, which don't go with other fields, holds the field
+    UnsupportedOpError(LeftVar),
+    /// The value can't be converted to the type of the field it is compared against,
+    /// holds the field and the value
+    ValueTypeError(LeftVar, String),
 }
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = || LeftVar::ALL.iter().map(|field| field.name());
+        match self {
+            ExpressionError::LikeParseError => write!(f, "`like` needs a value to look for"),
+            ExpressionError::ExprParseError => write!(f, "unexpected input"),
+            ExpressionError::ArgParseError => write!(f, "expected a field name"),
+            ExpressionError::OpParseError => {
+                write!(
+                    f,
+                    "expected one of =, !=, <, <=, >, >=, like, has, any or all"
+                )
+            }
+            ExpressionError::UnexpectedEndError => write!(f, "the query ends too soon"),
+            ExpressionError::UnbalancedParensError => write!(f, "unbalanced parenthesis"),
+            ExpressionError::UnterminatedStringError => write!(f, "unterminated string"),
+            ExpressionError::NumberParseError => write!(f, "expected a number"),
+            ExpressionError::UnknownFieldError(name) => {
+                write!(f, "unknown field '{}'", name)?;
+                suggest(f, did_you_mean(name, fields()))
+            }
+            ExpressionError::UnknownColumnError(name) => {
+                write!(f, "unknown column '{}'", name)?;
+                suggest(f, did_you_mean(name, fields()))
+            }
+            ExpressionError::GroupByWithoutCountError => {
+                write!(f, "`group by` only goes with `select count(*)`")
+            }
+            ExpressionError::UnsupportedOpError(LeftVar::Tags) => {
+                write!(f, "tags are compared with has, any or all")
+            }
+            ExpressionError::UnsupportedOpError(field) => {
+                write!(
+                    f,
+                    "has, any and all only go with tags, not {}",
+                    field.name()
+                )
+            }
+            ExpressionError::ValueTypeError(LeftVar::Status, value) => {
+                write!(f, "'{}' is neither a status nor true or false", value)?;
+                let statuses = Status::ALL.iter().map(|status| status.name());
+                suggest(f, did_you_mean(value, statuses))
+            }
+            ExpressionError::ValueTypeError(LeftVar::Priority, value) => {
+                write!(f, "'{}' is not a priority", value)?;
+                let priorities = Priority::ALL.iter().map(|priority| priority.name());
+                suggest(f, did_you_mean(value, priorities))
+            }
+            ExpressionError::ValueTypeError(LeftVar::IsBlocked, value) => {
+                write!(f, "'{}' is neither true nor false", value)
+            }
+            ExpressionError::ValueTypeError(LeftVar::Date, value) => {
+                write!(f, "'{}' is not a date", value)
+            }
+            ExpressionError::ValueTypeError(field, value) => {
+                write!(f, "'{}' is not a valid {}", value, field.name())
+            }
+        }
+    }
+}
+
+impl Error for ExpressionError {}
+
+fn suggest(f: &mut fmt::Formatter<'_>, suggestion: Option<&str>) -> fmt::Result {
+    match suggestion {
+        Some(suggestion) => write!(f, ", did you mean '{}'?", suggestion),
+        None => Ok(()),
+    }
+}
+
+/// [ExpressionError] along with the query it was found in and where
+#[derive(Debug, Clone)]
+pub struct QueryError {
+    pub kind: ExpressionError,
+    pub query: String,
+    /// Byte range of the offending part of the query, empty past its end when it ends too soon
+    pub span: Range<usize>,
+}
+
+/// The message followed by the query with the offending part underlined, the way rustc does:
+///
+/// ```text
+/// unknown field 'stat', did you mean 'status'?
+///   |
+///   | select * where stat=done
+///   |                ^^^^
+/// ```
+impl Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // carets line up with chars, not bytes
+        let padding = self.query[..self.span.start].chars().count();
+        let width = self.query[self.span.clone()].chars().count().max(1);
+        write!(
+            f,
+            "{}\n  |\n  | {}\n  | {}{}",
+            self.kind,
+            self.query,
+            " ".repeat(padding),
+            "^".repeat(width)
+        )
+    }
+}
+
+/// The message already tells what [ExpressionError] it is, so it is not a source
+impl Error for QueryError {}
+
+/// Failures of reading or writing the persisted task list.
+///
+/// The underlying errors are kept as messages so the enum stays [Clone] like the rest.
+#[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
+pub enum StorageError {
+    IoError(String),
+    FormatError(String),
+    DatabaseError(String),
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::IoError(e) => write!(f, "i/o error: {}", e),
+            StorageError::FormatError(e) => write!(f, "malformed task file: {}", e),
+            StorageError::DatabaseError(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl Error for StorageError {}
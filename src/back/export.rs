@@ -0,0 +1,285 @@
+#![allow(dead_code)]
+use super::task_model::{Priority, Status};
+use super::Task;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What `export` renders the tasks as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Graphviz digraph
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+    /// Mermaid Gantt chart
+    Gantt,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [
+        ExportFormat::Dot,
+        ExportFormat::Mermaid,
+        ExportFormat::Gantt,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Dot => "dot",
+            ExportFormat::Mermaid => "mermaid",
+            ExportFormat::Gantt => "gantt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        let name = name.trim().to_lowercase();
+        ExportFormat::ALL
+            .into_iter()
+            .find(|format| format.name() == name)
+    }
+
+    /// Renders the tasks along with what they wait on and their subtasks,
+    /// links to tasks that are not among them are left out.
+    ///
+    /// Dates are shown in the task's zone or `default_zone`.
+    pub fn render(&self, tasks: &[Task], default_zone: Tz) -> String {
+        match self {
+            ExportFormat::Dot => dot(tasks),
+            ExportFormat::Mermaid => mermaid(tasks),
+            ExportFormat::Gantt => gantt(tasks, default_zone),
+        }
+    }
+}
+
+/// Fill colour of the nodes of tasks in `status`
+fn colour(status: Status) -> &'static str {
+    match status {
+        Status::Todo => "#ffffff",
+        Status::InProgress => "#cfe2ff",
+        Status::Blocked => "#f8d7da",
+        Status::Done => "#d1e7dd",
+        Status::Cancelled => "#e2e3e5",
+    }
+}
+
+/// Categories of the tasks in the order they first come up, along with their tasks
+fn clusters(tasks: &[Task]) -> Vec<(&str, Vec<&Task>)> {
+    let mut clusters: Vec<(&str, Vec<&Task>)> = Vec::new();
+    for task in tasks {
+        match clusters
+            .iter_mut()
+            .find(|(category, _)| *category == task.category)
+        {
+            Some((_, tasks)) => tasks.push(task),
+            None => clusters.push((&task.category, vec![task])),
+        }
+    }
+    clusters
+}
+
+/// Ids of the tasks at both ends of a line, `(from, to)`
+type Link = (u64, u64);
+
+/// Links from the awaited tasks to the ones waiting on them,
+/// and from the parents to their subtasks
+fn links(tasks: &[Task]) -> (Vec<Link>, Vec<Link>) {
+    let ids: BTreeSet<u64> = tasks.iter().map(|task| task.id).collect();
+    let mut waits = Vec::new();
+    let mut subtasks = Vec::new();
+    for task in tasks {
+        for blocker in task.blocked_by.iter().filter(|id| ids.contains(id)) {
+            waits.push((*blocker, task.id));
+        }
+        if let Some(parent) = task.parent.filter(|id| ids.contains(id)) {
+            subtasks.push((parent, task.id));
+        }
+    }
+    (waits, subtasks)
+}
+
+/// ```text
+/// digraph tasks {
+///     subgraph cluster_0 {
+///         label="work";
+///         t1 [label="#1 design", fillcolor="#d1e7dd"];
+///     }
+///     t1 -> t2;
+/// }
+/// ```
+///
+/// Arrows point from a task to the ones waiting on it, dashed lines lead to subtasks.
+fn dot(tasks: &[Task]) -> String {
+    let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+
+    let mut lines = vec![
+        "digraph tasks {".to_string(),
+        "    rankdir=LR;".to_string(),
+        "    node [shape=box, style=filled];".to_string(),
+    ];
+    for (i, (category, tasks)) in clusters(tasks).into_iter().enumerate() {
+        lines.push(format!("    subgraph cluster_{} {{", i));
+        lines.push(format!("        label=\"{}\";", escape(category)));
+        for task in tasks {
+            lines.push(format!(
+                "        t{} [label=\"#{} {}\", fillcolor=\"{}\"];",
+                task.id,
+                task.id,
+                escape(&task.name),
+                colour(task.status)
+            ));
+        }
+        lines.push("    }".to_string());
+    }
+
+    let (waits, subtasks) = links(tasks);
+    for (from, to) in waits {
+        lines.push(format!("    t{} -> t{};", from, to));
+    }
+    for (parent, subtask) in subtasks {
+        lines.push(format!(
+            "    t{} -> t{} [style=dashed, arrowhead=none];",
+            parent, subtask
+        ));
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Mermaid takes `#code;` entities in labels, so `#` and quotes are written as ones
+fn mermaid_escape(text: &str) -> String {
+    text.replace('#', "#35;").replace('"', "#quot;")
+}
+
+/// ```text
+/// flowchart LR
+///     subgraph c0 ["work"]
+///         t1["#35;1 design"]
+///     end
+///     t1 --> t2
+///     class t1 done
+/// ```
+///
+/// Arrows point from a task to the ones waiting on it, dotted lines lead to subtasks.
+fn mermaid(tasks: &[Task]) -> String {
+    let mut lines = vec!["flowchart LR".to_string()];
+    for (i, (category, tasks)) in clusters(tasks).into_iter().enumerate() {
+        lines.push(format!(
+            "    subgraph c{} [\"{}\"]",
+            i,
+            mermaid_escape(category)
+        ));
+        for task in tasks {
+            lines.push(format!(
+                "        t{}[\"{}\"]",
+                task.id,
+                mermaid_escape(&format!("#{} {}", task.id, task.name))
+            ));
+        }
+        lines.push("    end".to_string());
+    }
+
+    let (waits, subtasks) = links(tasks);
+    for (from, to) in waits {
+        lines.push(format!("    t{} --> t{}", from, to));
+    }
+    for (parent, subtask) in subtasks {
+        lines.push(format!("    t{} -.- t{}", parent, subtask));
+    }
+
+    // status names have dashes, which class names can't
+    let class = |status: Status| status.name().replace('-', "_");
+    for status in Status::ALL {
+        let ids: Vec<String> = tasks
+            .iter()
+            .filter(|task| task.status == status)
+            .map(|task| format!("t{}", task.id))
+            .collect();
+        if !ids.is_empty() {
+            lines.push(format!(
+                "    classDef {} fill:{}",
+                class(status),
+                colour(status)
+            ));
+            lines.push(format!("    class {} {}", ids.join(","), class(status)));
+        }
+    }
+    lines.join("\n")
+}
+
+/// ```text
+/// gantt
+///     dateFormat YYYY-MM-DD HH:mm
+///     section work
+///     design :milestone, done, t1, 2015-09-05 00:00, 0d
+///     build :t2, after t1, 1d
+/// ```
+///
+/// Tasks only have a due date, so the ones waiting on others run from the end of those
+/// to their due date and the rest are milestones on it.
+/// A task due before the tasks it waits on gets no length rather than ending before it starts.
+/// Done tasks are marked `done`, started ones `active` and urgent ones `crit`.
+fn gantt(tasks: &[Task], default_zone: Tz) -> String {
+    let dates: BTreeMap<u64, DateTime<Utc>> =
+        tasks.iter().map(|task| (task.id, task.date)).collect();
+
+    let mut lines = vec![
+        "gantt".to_string(),
+        "    dateFormat YYYY-MM-DD HH:mm".to_string(),
+    ];
+    for (category, tasks) in clusters(tasks) {
+        // a colon would end the section name
+        lines.push(format!("    section {}", category.replace(':', " ")));
+        for task in tasks {
+            let mut tags = Vec::new();
+            match task.status {
+                Status::Done => tags.push("done".to_string()),
+                Status::InProgress => tags.push("active".to_string()),
+                _ => {}
+            }
+            if task.priority == Priority::Urgent {
+                tags.push("crit".to_string());
+            }
+
+            let due = task
+                .local_date(default_zone)
+                .format("%Y-%m-%d %H:%M")
+                .to_string();
+            let blockers: Vec<String> = task
+                .blocked_by
+                .iter()
+                .filter(|id| dates.contains_key(id))
+                .map(|id| format!("t{}", id))
+                .collect();
+            // mermaid starts the task once the last of them ends
+            let start = task.blocked_by.iter().filter_map(|id| dates.get(id)).max();
+            match start {
+                None => {
+                    tags.insert(0, "milestone".to_string());
+                    tags.extend([format!("t{}", task.id), due, "0d".to_string()]);
+                }
+                Some(start) => tags.extend([
+                    format!("t{}", task.id),
+                    format!("after {}", blockers.join(" ")),
+                    duration(task.date - *start),
+                ]),
+            }
+            lines.push(format!(
+                "    {} :{}",
+                task.name.replace([':', '#'], " "),
+                tags.join(", ")
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+/// `36h` for a day and a half, in the largest unit that divides it, `0d` if it is negative
+fn duration(length: Duration) -> String {
+    let minutes = length.num_minutes().max(0);
+    match minutes {
+        0 => "0d".to_string(),
+        _ if minutes % (24 * 60) == 0 => format!("{}d", minutes / (24 * 60)),
+        _ if minutes % 60 == 0 => format!("{}h", minutes / 60),
+        _ => format!("{}m", minutes),
+    }
+}
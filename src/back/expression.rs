@@ -1,18 +1,27 @@
 #![allow(dead_code)]
-use super::ExpressionError;
+use super::dates::DateParser;
+use super::task_model::{parse_task_date, Priority, Status};
+use super::{ExpressionError, QueryError};
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::CharIndices;
 
 /// Supported [Task] struct fields
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LeftVar {
     Name,
     Description,
     Date,
     Category,
-    Done,
+    Status,
+    Priority,
+    Tags,
+    /// Computed, whether the task waits on a task that is still open
+    IsBlocked,
 }
 
 /// Supported operators
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Op {
     Equals,
     NotEquals,
@@ -21,6 +30,47 @@ pub enum Op {
     GrEquals,
     LeEquals,
     Like,
+    /// `tags has a`, the task has the tag
+    Has,
+    /// `tags any (a, b)`, the task has at least one of the tags
+    Any,
+    /// `tags all (a, b)`, the task has every one of the tags
+    All,
+}
+
+impl Op {
+    /// Whether the operator compares sets of tags rather than single values
+    pub fn is_tag_op(&self) -> bool {
+        matches!(self, Op::Has | Op::Any | Op::All)
+    }
+}
+
+impl LeftVar {
+    /// Every field, in the order `select *` shows them
+    pub const ALL: [LeftVar; 8] = [
+        LeftVar::Name,
+        LeftVar::Description,
+        LeftVar::Date,
+        LeftVar::Category,
+        LeftVar::Status,
+        LeftVar::Priority,
+        LeftVar::Tags,
+        LeftVar::IsBlocked,
+    ];
+
+    /// Name of the field as written in queries
+    pub fn name(&self) -> &'static str {
+        match self {
+            LeftVar::Name => "name",
+            LeftVar::Description => "description",
+            LeftVar::Date => "date",
+            LeftVar::Category => "category",
+            LeftVar::Status => "status",
+            LeftVar::Priority => "priority",
+            LeftVar::Tags => "tags",
+            LeftVar::IsBlocked => "is_blocked",
+        }
+    }
 }
 
 /// Matches the incoming string with the according [Task](crate::back::Task) field [LeftVar]
@@ -30,8 +80,11 @@ fn match_field(arg: &str) -> Result<LeftVar, ExpressionError> {
         "description" => Ok(LeftVar::Description),
         "date" => Ok(LeftVar::Date),
         "category" => Ok(LeftVar::Category),
-        "status" => Ok(LeftVar::Done),
-        _ => Err(ExpressionError::ArgParseError),
+        "status" => Ok(LeftVar::Status),
+        "priority" => Ok(LeftVar::Priority),
+        "tags" => Ok(LeftVar::Tags),
+        "is_blocked" => Ok(LeftVar::IsBlocked),
+        _ => Err(ExpressionError::UnknownFieldError(arg.to_string())),
     }
 }
 
@@ -52,66 +105,667 @@ fn match_op(op: &str) -> Result<Op, ExpressionError> {
     }
 }
 
-/// Parses the expression [String] in format
+/// Parsed `select` filter
 ///
-/// `"arg=something"`
+/// `category=work or not (status=true and date<2020-12-12 00:00)` becomes
 ///
-/// into a [Result] tuple: ([LeftVar], [Op], [String])
+/// `Or(Comparison(Category, Equals, "work"), Not(And(Comparison(..), Comparison(..))))`
 ///
-/// Or returns an [ExpressionError] when failed.
-fn parse_expr(expression: String) -> Result<(LeftVar, Op, String), ExpressionError> {
-    let op: String;
+/// The tags compared with [Op::Any] and [Op::All] are joined with commas, see [split_tags].
+#[derive(Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Comparison(LeftVar, Op, String),
+}
 
-    if expression.contains("like") {
-        op = String::from("like");
-    } else {
-        // filters chars in string if they're in the list of supported operators
-        // "arg=test" => "="
-        // "arg>=test" => ">="
-        op = expression
-            .chars()
-            .filter(|c| match_op(&c.to_string()).is_ok())
-            .collect::<String>();
+impl Expr {
+    /// Whether any of the comparisons is on `field`
+    pub fn mentions(&self, field: LeftVar) -> bool {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.mentions(field) || right.mentions(field)
+            }
+            Expr::Not(inner) => inner.mentions(field),
+            Expr::Comparison(leftvar, _, _) => *leftvar == field,
+        }
     }
+}
 
-    // attempts to split arg into two parts by a separator
-    let args: Vec<&str> = expression
-        .split(&op)
-        .map(|arg| arg.trim().trim_matches('"'))
-        .filter(|arg| !arg.is_empty())
-        .collect();
+/// Direction of an `order by` key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
 
-    let op = match_op(&op)?;
+/// Columns a `select` returns
+#[derive(Debug, PartialEq)]
+pub enum Projection {
+    /// `select *`
+    All,
+    /// `select name, date`
+    Columns(Vec<LeftVar>),
+    /// `select count(*)`
+    Count,
+}
 
-    if args.len() != 2 {
-        return Err(ExpressionError::ExprParseError);
+impl Projection {
+    /// Columns of the returned rows, none for aggregates
+    pub fn columns(&self) -> &[LeftVar] {
+        match self {
+            Projection::All => &LeftVar::ALL,
+            Projection::Columns(columns) => columns,
+            Projection::Count => &[],
+        }
     }
+}
 
-    let (left, right) = (
-        args.first().unwrap().to_string(),
-        args.last().unwrap().to_string(),
-    );
+/// Span of time dates are grouped by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateBucket {
+    Day,
+    /// ISO week, starting on monday
+    Week,
+    Month,
+}
+
+/// What `group by` puts tasks together by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    Field(LeftVar),
+    Date(DateBucket),
+}
 
-    let left = match_field(&left)?;
+/// Parsed `select (* | columns) [where ...] [order by ...] [limit N] [offset M]` query
+#[derive(Debug)]
+pub struct Query {
+    pub projection: Projection,
+    pub filter: Option<Expr>,
+    /// Only allowed together with [Projection::Count]
+    pub group_by: Option<GroupBy>,
+    pub order_by: Vec<(LeftVar, SortOrder)>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
 
-    Ok((left, op, right))
+/// Smallest meaningful pieces of a query
+#[derive(Debug, PartialEq)]
+enum Token {
+    /// Unquoted field names and values
+    Word(String),
+    /// Double-quoted value with the quotes stripped and escapes resolved,
+    /// never treated as a keyword or an operator
+    Str(String),
+    Op(String),
+    And,
+    Or,
+    Not,
+    Like,
+    LParen,
+    RParen,
+    Comma,
 }
 
-/// Parses the string containing args into a [Vec] of tuples ([LeftVar], [Op], [String])
-pub fn parse_args(args: &str) -> Result<Vec<(LeftVar, Op, String)>, ExpressionError> {
-    let parsed: Vec<Result<(LeftVar, Op, String), ExpressionError>> = args
-        .split("and")
-        .map(|arg| arg.trim())
-        .map(|arg| parse_expr(arg.to_string()))
-        .collect();
+/// Byte range of a part of the query, errors point at it
+pub type Span = Range<usize>;
 
-    if parsed.iter().any(|arg| arg.is_err()) {
-        return Err(ExpressionError::ArgParseError);
+/// Characters operators are made of, see [match_op]
+fn is_op_char(c: char) -> bool {
+    matches!(c, '<' | '>' | '=' | '!')
+}
+
+/// Splits the query into [Token]s along with the [Span]s they were read from
+///
+/// Words are separated by whitespace, parentheses and operators.
+/// Everything between double quotes is a single [Token::Str],
+/// inside of it `\"` stands for a quote and `\\` for a backslash.
+fn tokenize(input: &str) -> Result<Vec<(Token, Span)>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    let position = |chars: &mut Peekable<CharIndices>| chars.peek().map_or(input.len(), |c| c.0);
+
+    while let Some(&(start, c)) = chars.peek() {
+        let token = match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' => {
+                chars.next();
+                Token::LParen
+            }
+            ')' => {
+                chars.next();
+                Token::RParen
+            }
+            ',' => {
+                chars.next();
+                Token::Comma
+            }
+            '"' => {
+                chars.next();
+                let unterminated = || QueryError {
+                    kind: ExpressionError::UnterminatedStringError,
+                    query: input.to_string(),
+                    span: start..input.len(),
+                };
+                let mut literal = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => literal.push(escaped),
+                            None => return Err(unterminated()),
+                        },
+                        Some((_, c)) => literal.push(c),
+                        None => return Err(unterminated()),
+                    }
+                }
+                Token::Str(literal)
+            }
+            _ if is_op_char(c) => {
+                let mut op = String::new();
+                while let Some((_, c)) = chars.next_if(|(_, c)| is_op_char(*c)) {
+                    op.push(c);
+                }
+                Token::Op(op)
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some((_, c)) = chars.next_if(|(_, c)| {
+                    !c.is_whitespace() && !is_op_char(*c) && !matches!(c, '(' | ')' | ',' | '"')
+                }) {
+                    word.push(c);
+                }
+                match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "like" => Token::Like,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        tokens.push((token, start..position(&mut chars)));
     }
 
-    let unwrapped = parsed.into_iter().map(|arg| arg.unwrap()).collect();
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the [Token]s, from the loosest binding rule to the tightest:
+///
+/// ```text
+/// query      := "select" ("*" | "count" "(" "*" ")" | field ("," field)*) ("where" or)?
+///               ("group" "by" group)? ("order" "by" key ("," key)*)?
+///               ("limit" number)? ("offset" number)?
+/// group      := "date" ("day" | "week" | "month")? | field
+/// key        := field ("asc" | "desc")?
+/// or         := and ("or" and)*
+/// and        := unary ("and" unary)*
+/// unary      := "not" unary | primary
+/// primary    := "(" or ")" | comparison
+/// comparison := field (op | "like") value | "tags" ("has" value | ("any" | "all") tags)
+/// value      := (word | string)+
+/// tags       := "(" (word | string) ("," (word | string))* ")" | value
+/// ```
+///
+/// `group by`, `order by`, `limit` and `offset` only act as keywords where a value ends,
+/// so they end an unquoted value just like `and` does.
+///
+/// Errors point at the token they are about, or past the end of the query when it ends too soon.
+struct Parser<'a> {
+    query: &'a str,
+    tokens: Vec<(Token, Span)>,
+    pos: usize,
+    /// Resolves the values compared against `date`, without it they are only checked
+    dates: Option<&'a DateParser>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(query: &'a str, dates: Option<&'a DateParser>) -> Result<Self, QueryError> {
+        Ok(Self {
+            query,
+            tokens: tokenize(query)?,
+            pos: 0,
+            dates,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos).map(|(token, _)| token);
+        self.pos += 1;
+        token
+    }
+
+    /// Span of the token at `pos`, an empty one past the end of the query for the missing ones
+    fn span_at(&self, pos: usize) -> Span {
+        match self.tokens.get(pos) {
+            Some((_, span)) => span.clone(),
+            None => self.query.len()..self.query.len(),
+        }
+    }
+
+    /// Error about the token at `pos`
+    fn error_at(&self, pos: usize, kind: ExpressionError) -> QueryError {
+        self.error_spanning(self.span_at(pos), kind)
+    }
+
+    /// Error about the token just taken with [Parser::next]
+    fn error(&self, kind: ExpressionError) -> QueryError {
+        self.error_at(self.pos.saturating_sub(1), kind)
+    }
+
+    fn error_spanning(&self, span: Span, kind: ExpressionError) -> QueryError {
+        QueryError {
+            kind,
+            query: self.query.to_string(),
+            span,
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Checks if the word `offset` tokens ahead is the `keyword`, ignoring case
+    fn word_is(&self, offset: usize, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos + offset), Some((Token::Word(word), _)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_word(&mut self, keyword: &str) -> bool {
+        if self.word_is(0, keyword) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Checks if a query clause following the filter starts here
+    fn at_clause(&self) -> bool {
+        (self.word_is(0, "order") && self.word_is(1, "by"))
+            || (self.word_is(0, "group") && self.word_is(1, "by"))
+            || self.word_is(0, "limit")
+            || self.word_is(0, "offset")
+    }
+
+    /// Takes a field name, `unexpected` is the error for any other token
+    fn parse_field(&mut self, unexpected: ExpressionError) -> Result<LeftVar, QueryError> {
+        match self.next() {
+            Some(Token::Word(field)) => {
+                let field = field.clone();
+                match_field(&field).map_err(|kind| self.error(kind))
+            }
+            Some(_) => Err(self.error(unexpected)),
+            None => Err(self.error(ExpressionError::UnexpectedEndError)),
+        }
+    }
+
+    fn parse_query(&mut self) -> Result<Query, QueryError> {
+        if !self.eat_word("select") {
+            return Err(self.error_at(self.pos, ExpressionError::ExprParseError));
+        }
+
+        let projection = if self.eat_word("*") {
+            Projection::All
+        } else if self.word_is(0, "count")
+            && matches!(self.tokens.get(self.pos + 1), Some((Token::LParen, _)))
+        {
+            self.pos += 2;
+            if !self.eat_word("*") || !self.eat(&Token::RParen) {
+                return Err(self.error_at(self.pos, ExpressionError::ExprParseError));
+            }
+            Projection::Count
+        } else {
+            Projection::Columns(self.parse_columns()?)
+        };
+
+        let filter = if self.eat_word("where") {
+            Some(self.parse_or()?)
+        } else {
+            None
+        };
+
+        let mut group_by = None;
+        if self.word_is(0, "group") && self.word_is(1, "by") {
+            if projection != Projection::Count {
+                let span = self.span_at(self.pos).start..self.span_at(self.pos + 1).end;
+                return Err(self.error_spanning(span, ExpressionError::GroupByWithoutCountError));
+            }
+            self.pos += 2;
+            group_by = Some(self.parse_group()?);
+        }
+
+        let mut order_by = Vec::new();
+        if self.word_is(0, "order") && self.word_is(1, "by") {
+            if projection == Projection::Count {
+                return Err(self.error_at(self.pos, ExpressionError::ExprParseError));
+            }
+            self.pos += 2;
+            loop {
+                let field = self.parse_field(ExpressionError::ArgParseError)?;
+                let order = if self.eat_word("desc") {
+                    SortOrder::Desc
+                } else {
+                    self.eat_word("asc");
+                    SortOrder::Asc
+                };
+                order_by.push((field, order));
+
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let limit = if self.eat_word("limit") {
+            Some(self.parse_number()?)
+        } else {
+            None
+        };
+
+        let offset = if self.eat_word("offset") {
+            self.parse_number()?
+        } else {
+            0
+        };
+
+        Ok(Query {
+            projection,
+            filter,
+            group_by,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn parse_group(&mut self) -> Result<GroupBy, QueryError> {
+        let field = self.parse_field(ExpressionError::ArgParseError)?;
+
+        if field != LeftVar::Date {
+            return Ok(GroupBy::Field(field));
+        }
+
+        let bucket = if self.eat_word("week") {
+            DateBucket::Week
+        } else if self.eat_word("month") {
+            DateBucket::Month
+        } else {
+            self.eat_word("day");
+            DateBucket::Day
+        };
+        Ok(GroupBy::Date(bucket))
+    }
+
+    fn parse_columns(&mut self) -> Result<Vec<LeftVar>, QueryError> {
+        let mut columns = Vec::new();
+        loop {
+            let column = match self.next() {
+                Some(Token::Word(column)) => {
+                    let column = column.clone();
+                    match_field(&column).map_err(|_| {
+                        self.error(ExpressionError::UnknownColumnError(column.clone()))
+                    })?
+                }
+                Some(_) => return Err(self.error(ExpressionError::ExprParseError)),
+                None => return Err(self.error(ExpressionError::UnexpectedEndError)),
+            };
+            columns.push(column);
+
+            if !self.eat(&Token::Comma) {
+                return Ok(columns);
+            }
+        }
+    }
+
+    fn expect_end(&self) -> Result<(), QueryError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(Token::RParen) => {
+                Err(self.error_at(self.pos, ExpressionError::UnbalancedParensError))
+            }
+            Some(_) => Err(self.error_at(self.pos, ExpressionError::ExprParseError)),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<usize, QueryError> {
+        match self.next() {
+            Some(Token::Word(number)) => number
+                .parse()
+                .map_err(|_| self.error(ExpressionError::NumberParseError)),
+            Some(_) => Err(self.error(ExpressionError::NumberParseError)),
+            None => Err(self.error(ExpressionError::UnexpectedEndError)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, QueryError> {
+        if self.eat(&Token::LParen) {
+            let open = self.pos - 1;
+            let expr = self.parse_or()?;
+            if !self.eat(&Token::RParen) {
+                return Err(self.error_at(open, ExpressionError::UnbalancedParensError));
+            }
+            return Ok(expr);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, QueryError> {
+        let field = self.parse_field(ExpressionError::ExprParseError)?;
+
+        let op = match self.next() {
+            Some(Token::Op(op)) => {
+                let op = op.clone();
+                match_op(&op).map_err(|kind| self.error(kind))?
+            }
+            Some(Token::Like) => Op::Like,
+            Some(Token::Word(word)) => match word.to_lowercase().as_str() {
+                "has" => Op::Has,
+                "any" => Op::Any,
+                "all" => Op::All,
+                _ => return Err(self.error(ExpressionError::OpParseError)),
+            },
+            Some(_) => return Err(self.error(ExpressionError::OpParseError)),
+            None => return Err(self.error(ExpressionError::UnexpectedEndError)),
+        };
+        if op.is_tag_op() != (field == LeftVar::Tags) {
+            return Err(self.error(ExpressionError::UnsupportedOpError(field)));
+        }
+
+        if matches!(op, Op::Any | Op::All) && self.peek() == Some(&Token::LParen) {
+            return self.parse_tag_list(op);
+        }
+
+        let first = self.pos;
+        let mut value = Vec::new();
+        while !self.at_clause() {
+            let Some(Token::Word(word) | Token::Str(word)) = self.peek() else {
+                break;
+            };
+            value.push(word.clone());
+            self.pos += 1;
+        }
+
+        if value.is_empty() {
+            let kind = match (self.peek(), &op) {
+                (None, Op::Like) => ExpressionError::LikeParseError,
+                (None, _) => ExpressionError::UnexpectedEndError,
+                _ => ExpressionError::ExprParseError,
+            };
+            return Err(self.error_at(self.pos, kind));
+        }
+
+        let span = self.span_at(first).start..self.span_at(self.pos - 1).end;
+        let value = self
+            .convert_value(field, op, value.join(" "))
+            .map_err(|kind| self.error_spanning(span, kind))?;
+        Ok(Expr::Comparison(field, op, value))
+    }
+
+    /// `(a, b)` after `tags any` or `tags all`
+    fn parse_tag_list(&mut self, op: Op) -> Result<Expr, QueryError> {
+        let open = self.pos;
+        self.pos += 1;
+
+        let mut tags = Vec::new();
+        loop {
+            match self.next() {
+                Some(Token::Word(tag) | Token::Str(tag)) => tags.push(tag.trim().to_string()),
+                Some(_) => return Err(self.error(ExpressionError::ExprParseError)),
+                None => return Err(self.error_at(open, ExpressionError::UnbalancedParensError)),
+            }
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::RParen) => break,
+                Some(_) => return Err(self.error(ExpressionError::ExprParseError)),
+                None => return Err(self.error_at(open, ExpressionError::UnbalancedParensError)),
+            }
+        }
+
+        Ok(Expr::Comparison(LeftVar::Tags, op, tags.join(",")))
+    }
+
+    /// Brings a value compared against `field` to the form the storages compare it in.
+    ///
+    /// `status` takes a boolean asking whether the task is done (`true`, `yes`, `on`, `1` and
+    /// their opposites) or a [Status], see [Status::parse]. `is_blocked` takes the same booleans.
+    /// `priority` takes a [Priority] name or rank. Dates are resolved to the UTC
+    /// [CANONICAL_FORMAT](crate::back::dates::CANONICAL_FORMAT) when the parser has a
+    /// [DateParser], without one they have to be written in it already. Text is taken as is.
+    fn convert_value(
+        &self,
+        field: LeftVar,
+        op: Op,
+        value: String,
+    ) -> Result<String, ExpressionError> {
+        match (field, op) {
+            (LeftVar::Date, _) => match self.dates {
+                Some(dates) => dates
+                    .canonicalize(&value)
+                    .map_err(|_| ExpressionError::ValueTypeError(field, value)),
+                None => match parse_task_date(value.clone()) {
+                    Ok(_) => Ok(value),
+                    Err(_) => Err(ExpressionError::ValueTypeError(field, value)),
+                },
+            },
+            (LeftVar::Status | LeftVar::IsBlocked, Op::Like) => Ok(value.to_lowercase()),
+            (LeftVar::Status | LeftVar::IsBlocked, _) => match value.trim().to_lowercase().as_str()
+            {
+                "true" | "yes" | "on" | "1" => Ok("true".to_string()),
+                "false" | "no" | "off" | "0" => Ok("false".to_string()),
+                name if field == LeftVar::Status => Status::parse(name)
+                    .map(|status| status.name().to_string())
+                    .ok_or(ExpressionError::ValueTypeError(field, value)),
+                _ => Err(ExpressionError::ValueTypeError(field, value)),
+            },
+            (LeftVar::Priority, Op::Like) => Ok(value.to_lowercase()),
+            (LeftVar::Priority, _) => Priority::parse(&value)
+                .map(|priority| priority.name().to_string())
+                .ok_or(ExpressionError::ValueTypeError(field, value)),
+            _ => Ok(value),
+        }
+    }
+}
+
+/// Tags compared with [Op::Any] and [Op::All], or set with the `tags=` option
+pub fn split_tags(tags: &str) -> impl Iterator<Item = String> + '_ {
+    tags.split(',')
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+}
+
+/// Parses the filter part of a `select` query into an [Expr] tree
+///
+/// `and` binds tighter than `or`, `not` applies to the closest comparison or parenthesised group.
+pub fn parse_args(args: &str) -> Result<Expr, QueryError> {
+    let mut parser = Parser::new(args, None)?;
+
+    let expr = parser.parse_or()?;
+    parser.expect_end()?;
+
+    Ok(expr)
+}
+
+/// Parses a whole `select` query, see [Parser] for the grammar
+pub fn parse_query(query: &str) -> Result<Query, QueryError> {
+    parse_query_with(query, None)
+}
+
+/// Like [parse_query], resolving the dates compared against `date` with `dates`
+pub fn parse_query_with(query: &str, dates: Option<&DateParser>) -> Result<Query, QueryError> {
+    let mut parser = Parser::new(query, dates)?;
+
+    let query = parser.parse_query()?;
+    parser.expect_end()?;
+
+    Ok(query)
+}
+
+/// The candidate closest to a mistyped `word`, if any is close enough to be what was meant
+pub fn did_you_mean<'a>(
+    word: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let word = word.to_lowercase();
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(&word, candidate), candidate))
+        .filter(|(distance, candidate)| {
+            *distance <= 2.max(word.chars().count() / 3) || candidate.starts_with(&word)
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`, in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
 
-    Ok(unwrapped)
+    row[b.len()]
 }
 
 /// Checks if first word in `input` is equal to `contains`
@@ -122,7 +776,7 @@ pub fn parse_args(args: &str) -> Result<Vec<(LeftVar, Op, String)>, ExpressionEr
 pub fn command_equals(command: &str, other: &str) -> Result<bool, ExpressionError> {
     let command = command.split_whitespace().collect::<Vec<&str>>();
 
-    if let None = command.first() {
+    if command.is_empty() {
         return Err(ExpressionError::ArgParseError);
     }
 
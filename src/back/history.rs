@@ -0,0 +1,143 @@
+#![allow(dead_code)]
+use super::storage::Storage;
+use super::{Task, TaskMgrError};
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+
+/// How many commands can be undone unless told otherwise
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// A single write to the [Storage], holding what it replaced so it can be taken back
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// The task as it was stored, with its id
+    Insert(Task),
+    /// Boxed, holding two tasks it would be twice as big as the others
+    Update {
+        before: Box<Task>,
+        after: Box<Task>,
+    },
+    Remove(Task),
+}
+
+impl Change {
+    /// Writes the change (again)
+    pub fn apply<S: Storage + ?Sized>(&self, storage: &mut S) -> Result<(), TaskMgrError> {
+        match self {
+            Change::Insert(task) => storage.insert(task.clone()).map(|_| ()),
+            Change::Update { after, .. } => storage.update(Task::clone(after)),
+            Change::Remove(task) => storage.remove(task.id).map(|_| ()),
+        }
+    }
+
+    /// Puts back what the change replaced, removed tasks come back with their ids
+    pub fn revert<S: Storage + ?Sized>(&self, storage: &mut S) -> Result<(), TaskMgrError> {
+        match self {
+            Change::Insert(task) => storage.remove(task.id).map(|_| ()),
+            Change::Update { before, .. } => storage.update(Task::clone(before)),
+            Change::Remove(task) => storage.insert(task.clone()).map(|_| ()),
+        }
+    }
+}
+
+/// `added #2 build`, `changed #1 design` or `deleted #3 docs`
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Insert(task) => write!(f, "added #{} {}", task.id, task.name),
+            Change::Update { before, .. } => write!(f, "changed #{} {}", before.id, before.name),
+            Change::Remove(task) => write!(f, "deleted #{} {}", task.id, task.name),
+        }
+    }
+}
+
+/// Everything a command wrote, undone and redone as a whole
+#[derive(Debug, Clone)]
+pub struct Revision {
+    /// The command as it was entered
+    pub command: String,
+    pub changes: Vec<Change>,
+}
+
+impl Revision {
+    pub fn apply<S: Storage + ?Sized>(&self, storage: &mut S) -> Result<(), TaskMgrError> {
+        for change in &self.changes {
+            change.apply(storage)?;
+        }
+        Ok(())
+    }
+
+    /// Reverts the changes last to first
+    pub fn revert<S: Storage + ?Sized>(&self, storage: &mut S) -> Result<(), TaskMgrError> {
+        for change in self.changes.iter().rev() {
+            change.revert(storage)?;
+        }
+        Ok(())
+    }
+}
+
+/// `delete build; cascade: deleted #2 build, deleted #3 docs`
+impl Display for Revision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let changes: Vec<String> = self.changes.iter().map(Change::to_string).collect();
+        write!(f, "{}: {}", self.command, changes.join(", "))
+    }
+}
+
+/// Commands that can be undone, newest last, and the undone ones that can be redone.
+///
+/// Only the last `limit` commands are kept, a new command drops whatever could be redone.
+#[derive(Debug, Clone)]
+pub struct History {
+    undo: VecDeque<Revision>,
+    redo: Vec<Revision>,
+    limit: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LIMIT)
+    }
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit,
+        }
+    }
+
+    pub fn record(&mut self, revision: Revision) {
+        self.redo.clear();
+        self.undo.push_back(revision);
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Takes the latest command off to be undone, it can be redone afterwards
+    pub fn undo(&mut self) -> Option<&Revision> {
+        let revision = self.undo.pop_back()?;
+        self.redo.push(revision);
+        self.redo.last()
+    }
+
+    /// Takes the latest undone command off to be redone, it can be undone again afterwards
+    pub fn redo(&mut self) -> Option<&Revision> {
+        let revision = self.redo.pop()?;
+        self.undo.push_back(revision);
+        self.undo.back()
+    }
+
+    /// What [History::undo] would revert, the latest first
+    pub fn undoable(&self) -> impl Iterator<Item = &Revision> {
+        self.undo.iter().rev()
+    }
+
+    /// What [History::redo] would apply again, the latest undone first
+    pub fn redoable(&self) -> impl Iterator<Item = &Revision> {
+        self.redo.iter().rev()
+    }
+}
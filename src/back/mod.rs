@@ -1,7 +1,16 @@
+pub mod dates;
+pub mod dependencies;
 pub mod errors;
+pub mod export;
 pub mod expression;
+pub mod history;
+pub mod natural;
+pub mod recurrence;
+pub mod rows;
+pub mod storage;
 pub mod task_manager;
 pub mod task_model;
 mod tests;
+pub mod tree;
 use errors::*;
 use task_model::Task;
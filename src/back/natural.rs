@@ -0,0 +1,320 @@
+#![allow(dead_code)]
+use chrono::{
+    Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday,
+};
+
+/// Language the natural-language dates are written in
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Locale {
+    #[default]
+    English,
+    Russian,
+}
+
+impl Locale {
+    /// Looks a locale up by its language code, `en` or `ru`
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.trim().to_lowercase().as_str() {
+            "en" | "english" => Some(Self::English),
+            "ru" | "russian" => Some(Self::Russian),
+            _ => None,
+        }
+    }
+
+    fn classify(self, word: &str) -> Option<Word> {
+        if let Ok(amount) = word.parse::<u32>() {
+            return Some(Word::Number(amount));
+        }
+        if let Ok(time) = NaiveTime::parse_from_str(word, "%H:%M") {
+            return Some(Word::Time(time));
+        }
+
+        match self {
+            Self::English => english_word(word),
+            Self::Russian => russian_word(word),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+/// A word of the date phrase with the language taken out of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Word {
+    /// `at`, `on`, `в`: carries no meaning of its own
+    Filler,
+    /// `in`, `через`: the amount that follows is added
+    In,
+    /// `ago`, `назад`: the amount before it is subtracted
+    Ago,
+    Next,
+    This,
+    /// `today`, `tomorrow`, ... as the number of days from today
+    Day(i64),
+    Weekday(Weekday),
+    Unit(Unit),
+    Number(u32),
+    Time(NaiveTime),
+    /// `am` or `pm`, the latter being `true`
+    Meridiem(bool),
+}
+
+/// Parses a due date written in plain words, like `next friday 17:00`, `in 2 hours`
+/// or `через 3 дня в 10:00`, relative to `now`.
+///
+/// A phrase names a day (`tomorrow`, `friday`, `next week`) and/or a time (`17:00`, `5pm`, `noon`),
+/// and may shift it (`in 2 days`, `3 hours ago`). A named day without a time is at `default_time`.
+/// A bare or `this` weekday is its nearest occurrence, today included, `next` skips today.
+///
+/// Returns [None] when any word of the phrase is not understood.
+pub fn parse_natural(
+    text: &str,
+    now: NaiveDateTime,
+    locale: Locale,
+    default_time: NaiveTime,
+) -> Option<NaiveDateTime> {
+    let words = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .map(|word| split_meridiem(&word.to_lowercase(), locale))
+        .collect::<Option<Vec<Vec<Word>>>>()?
+        .into_iter()
+        .flatten()
+        .filter(|word| *word != Word::Filler)
+        .collect::<Vec<Word>>();
+
+    if words.is_empty() {
+        return None;
+    }
+
+    let today = now.date();
+    let mut date: Option<NaiveDate> = None;
+    let mut time: Option<NaiveTime> = None;
+    let mut shifts: Vec<(i64, Unit)> = Vec::new();
+
+    let mut rest = words.as_slice();
+    while !rest.is_empty() {
+        rest = match rest {
+            [Word::Day(days), rest @ ..] => {
+                set_once(&mut date, today.checked_add_signed(Duration::days(*days))?)?;
+                rest
+            }
+            [Word::This, Word::Weekday(weekday), rest @ ..]
+            | [Word::Weekday(weekday), rest @ ..] => {
+                set_once(&mut date, upcoming(today, *weekday, 0)?)?;
+                rest
+            }
+            [Word::Next, Word::Weekday(weekday), rest @ ..] => {
+                set_once(&mut date, upcoming(today, *weekday, 1)?)?;
+                rest
+            }
+            [Word::Next, Word::Unit(unit), rest @ ..] => {
+                match unit {
+                    Unit::Minute | Unit::Hour => shifts.push((1, *unit)),
+                    _ => set_once(&mut date, start_of_next(today, *unit)?)?,
+                }
+                rest
+            }
+            [Word::In, Word::Number(amount), Word::Unit(unit), rest @ ..] => {
+                shifts.push((*amount as i64, *unit));
+                rest
+            }
+            // `in an hour` spelled `через час`
+            [Word::In, Word::Unit(unit), rest @ ..] => {
+                shifts.push((1, *unit));
+                rest
+            }
+            [Word::Number(amount), Word::Unit(unit), Word::Ago, rest @ ..] => {
+                shifts.push((-(*amount as i64), *unit));
+                rest
+            }
+            [Word::Unit(unit), Word::Ago, rest @ ..] => {
+                shifts.push((-1, *unit));
+                rest
+            }
+            [Word::Number(hour), Word::Meridiem(pm), rest @ ..] => {
+                set_once(&mut time, twelve_hour(*hour, 0, *pm)?)?;
+                rest
+            }
+            [Word::Time(parsed), Word::Meridiem(pm), rest @ ..] => {
+                set_once(
+                    &mut time,
+                    twelve_hour(parsed.hour12().1, parsed.minute(), *pm)?,
+                )?;
+                rest
+            }
+            [Word::Time(parsed), rest @ ..] => {
+                set_once(&mut time, *parsed)?;
+                rest
+            }
+            _ => return None,
+        };
+    }
+
+    let mut resolved = match (date, time) {
+        (Some(date), time) => date.and_time(time.unwrap_or(default_time)),
+        (None, Some(time)) => today.and_time(time),
+        (None, None) => now,
+    };
+    for (amount, unit) in shifts {
+        resolved = shift(resolved, amount, unit)?;
+    }
+
+    Some(resolved)
+}
+
+/// Splits the `pm` off `5pm` and `5:30pm`, classifying the words on the way
+fn split_meridiem(word: &str, locale: Locale) -> Option<Vec<Word>> {
+    if let Some(classified) = locale.classify(word) {
+        return Some(vec![classified]);
+    }
+    if locale != Locale::English {
+        return None;
+    }
+
+    let (value, pm) = match word.strip_suffix("pm") {
+        Some(value) => (value, true),
+        None => (word.strip_suffix("am")?, false),
+    };
+    let value = match value.parse::<u32>() {
+        Ok(hour) => Word::Number(hour),
+        Err(_) => Word::Time(NaiveTime::parse_from_str(value, "%H:%M").ok()?),
+    };
+
+    Some(vec![value, Word::Meridiem(pm)])
+}
+
+fn english_word(word: &str) -> Option<Word> {
+    let word = match word {
+        "at" | "on" | "the" | "of" => Word::Filler,
+        "in" => Word::In,
+        "ago" => Word::Ago,
+        "next" => Word::Next,
+        "this" | "coming" => Word::This,
+        "today" => Word::Day(0),
+        "tomorrow" => Word::Day(1),
+        "yesterday" => Word::Day(-1),
+        "noon" | "midday" => Word::Time(NaiveTime::from_hms_opt(12, 0, 0)?),
+        "midnight" => Word::Time(NaiveTime::MIN),
+        "am" | "a.m." => Word::Meridiem(false),
+        "pm" | "p.m." => Word::Meridiem(true),
+        "a" | "an" | "one" => Word::Number(1),
+        "two" => Word::Number(2),
+        "three" => Word::Number(3),
+        "four" => Word::Number(4),
+        "five" => Word::Number(5),
+        "six" => Word::Number(6),
+        "seven" => Word::Number(7),
+        "eight" => Word::Number(8),
+        "nine" => Word::Number(9),
+        "ten" => Word::Number(10),
+        "min" | "mins" | "minute" | "minutes" => Word::Unit(Unit::Minute),
+        "h" | "hour" | "hours" => Word::Unit(Unit::Hour),
+        "day" | "days" => Word::Unit(Unit::Day),
+        "week" | "weeks" => Word::Unit(Unit::Week),
+        "month" | "months" => Word::Unit(Unit::Month),
+        "year" | "years" => Word::Unit(Unit::Year),
+        // chrono reads both the full and the three letter names
+        _ => Word::Weekday(word.parse::<Weekday>().ok()?),
+    };
+
+    Some(word)
+}
+
+fn russian_word(word: &str) -> Option<Word> {
+    let word = match word {
+        "в" | "во" | "на" | "к" => Word::Filler,
+        "через" => Word::In,
+        "назад" => Word::Ago,
+        "этот" | "эту" | "это" | "этой" | "этом" | "ближайший" | "ближайшую" => {
+            Word::This
+        }
+        "сегодня" => Word::Day(0),
+        "завтра" => Word::Day(1),
+        "послезавтра" => Word::Day(2),
+        "вчера" => Word::Day(-1),
+        "позавчера" => Word::Day(-2),
+        "полдень" => Word::Time(NaiveTime::from_hms_opt(12, 0, 0)?),
+        "полночь" => Word::Time(NaiveTime::MIN),
+        "один" | "одну" | "одна" => Word::Number(1),
+        "два" | "две" => Word::Number(2),
+        "три" => Word::Number(3),
+        "четыре" => Word::Number(4),
+        "пять" => Word::Number(5),
+        "день" | "дня" | "дней" | "сутки" => Word::Unit(Unit::Day),
+        "год" | "года" | "лет" => Word::Unit(Unit::Year),
+        _ if word.starts_with("следующ") => Word::Next,
+        _ if word.starts_with("минут") => Word::Unit(Unit::Minute),
+        _ if word.starts_with("час") => Word::Unit(Unit::Hour),
+        _ if word.starts_with("недел") => Word::Unit(Unit::Week),
+        _ if word.starts_with("месяц") => Word::Unit(Unit::Month),
+        _ if word.starts_with("понедельник") => Word::Weekday(Weekday::Mon),
+        _ if word.starts_with("вторник") => Word::Weekday(Weekday::Tue),
+        _ if word.starts_with("сред") => Word::Weekday(Weekday::Wed),
+        _ if word.starts_with("четверг") => Word::Weekday(Weekday::Thu),
+        _ if word.starts_with("пятниц") => Word::Weekday(Weekday::Fri),
+        _ if word.starts_with("суббот") => Word::Weekday(Weekday::Sat),
+        _ if word.starts_with("воскресень") => Word::Weekday(Weekday::Sun),
+        _ => return None,
+    };
+
+    Some(word)
+}
+
+/// Fails when the phrase names the day or the time twice
+fn set_once<T>(slot: &mut Option<T>, value: T) -> Option<()> {
+    match slot.replace(value) {
+        Some(_) => None,
+        None => Some(()),
+    }
+}
+
+/// First `weekday` at least `skip` days after `today`
+fn upcoming(today: NaiveDate, weekday: Weekday, skip: u64) -> Option<NaiveDate> {
+    let from = today.checked_add_days(Days::new(skip))?;
+    let ahead = (7 + weekday.num_days_from_monday() - from.weekday().num_days_from_monday()) % 7;
+    from.checked_add_days(Days::new(ahead as u64))
+}
+
+/// First day of the period after the one `today` is in
+fn start_of_next(today: NaiveDate, unit: Unit) -> Option<NaiveDate> {
+    match unit {
+        Unit::Day => today.checked_add_days(Days::new(1)),
+        Unit::Week => upcoming(today, Weekday::Mon, 1),
+        Unit::Month => today.with_day(1)?.checked_add_months(Months::new(1)),
+        Unit::Year => NaiveDate::from_ymd_opt(today.year() + 1, 1, 1),
+        Unit::Minute | Unit::Hour => None,
+    }
+}
+
+fn twelve_hour(hour: u32, minute: u32, pm: bool) -> Option<NaiveTime> {
+    if !(1..=12).contains(&hour) {
+        return None;
+    }
+    NaiveTime::from_hms_opt(hour % 12 + if pm { 12 } else { 0 }, minute, 0)
+}
+
+fn shift(date: NaiveDateTime, amount: i64, unit: Unit) -> Option<NaiveDateTime> {
+    let months = match unit {
+        Unit::Month => amount,
+        Unit::Year => amount.checked_mul(12)?,
+        Unit::Minute => return date.checked_add_signed(Duration::minutes(amount)),
+        Unit::Hour => return date.checked_add_signed(Duration::hours(amount)),
+        Unit::Day => return date.checked_add_signed(Duration::days(amount)),
+        Unit::Week => return date.checked_add_signed(Duration::weeks(amount)),
+    };
+
+    let forward = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+    match months >= 0 {
+        true => date.checked_add_months(forward),
+        false => date.checked_sub_months(forward),
+    }
+}
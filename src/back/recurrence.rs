@@ -0,0 +1,229 @@
+#![allow(dead_code)]
+use super::dates::{localize, DateParser};
+use super::TaskError;
+use chrono::{DateTime, Datelike, Days, Months, NaiveDateTime, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    fn unit(self) -> &'static str {
+        match self {
+            Frequency::Daily => "day",
+            Frequency::Weekly => "week",
+            Frequency::Monthly => "month",
+            Frequency::Yearly => "year",
+        }
+    }
+}
+
+/// How a task repeats, a subset of the iCalendar `RRULE`:
+///
+/// ```text
+/// rule := ("daily" | "weekly" | "monthly" | "yearly" | "every" number? unit)
+///         ("on" weekday ("," weekday)*)? ("until" date)? ("count" number)?
+/// unit := "day" | "days" | "week" | "weeks" | "month" | "months" | "year" | "years"
+/// ```
+///
+/// e.g. `weekly`, `every 2 weeks on mon,fri until 2024-12-31 count 10`.
+/// Weekdays only go with daily and weekly rules, not with every 7, 14, ... days. Monthly and yearly rules move to the day of
+/// the month the series started on, clamped to the last day of shorter months.
+/// An `until` without a time of day takes in the whole of that day, as it does in an `RRULE`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub weekdays: Vec<Weekday>,
+    /// No occurrence is scheduled after this instant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime<Utc>>,
+    /// Occurrences left, the current one included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+    /// Day of the month monthly and yearly occurrences fall on, that of the first one.
+    /// Set once the series moves on, so a day clamped in a short month comes back after it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day: Option<u32>,
+}
+
+impl Recurrence {
+    /// Parses a rule, the `until` date is read with `dates` in `zone`
+    pub fn parse(rule: &str, dates: &DateParser, zone: Option<Tz>) -> Result<Self, TaskError> {
+        let error = || TaskError::RecurrenceParseError(rule.trim().to_string());
+        let original: Vec<&str> = rule.split_whitespace().collect();
+        let lowered = rule.to_lowercase();
+        let words: Vec<&str> = lowered.split_whitespace().collect();
+
+        let (frequency, interval, mut rest) = match words.as_slice() {
+            ["daily", rest @ ..] => (Frequency::Daily, 1, rest),
+            ["weekly", rest @ ..] => (Frequency::Weekly, 1, rest),
+            ["monthly", rest @ ..] => (Frequency::Monthly, 1, rest),
+            ["yearly", rest @ ..] => (Frequency::Yearly, 1, rest),
+            ["every", interval, unit, rest @ ..] if interval.parse::<u32>().is_ok() => {
+                let interval = interval.parse::<u32>().map_err(|_| error())?;
+                (parse_unit(unit).ok_or_else(error)?, interval, rest)
+            }
+            ["every", unit, rest @ ..] => (parse_unit(unit).ok_or_else(error)?, 1, rest),
+            _ => return Err(error()),
+        };
+        if interval == 0 {
+            return Err(error());
+        }
+
+        let mut recurrence = Recurrence {
+            frequency,
+            interval,
+            weekdays: Vec::new(),
+            until: None,
+            count: None,
+            day: None,
+        };
+
+        if let ["on", tail @ ..] = rest {
+            let end = clause_end(tail);
+            recurrence.weekdays = tail[..end]
+                .join("")
+                .split(',')
+                .map(|day| day.parse::<Weekday>().map_err(|_| error()))
+                .collect::<Result<_, _>>()?;
+            if !matches!(frequency, Frequency::Daily | Frequency::Weekly) {
+                return Err(error());
+            }
+            // every 7 days lands on the same weekday each time, the others would never come up
+            if frequency == Frequency::Daily && interval.is_multiple_of(7) {
+                return Err(error());
+            }
+            rest = &tail[end..];
+        }
+
+        if let ["until", tail @ ..] = rest {
+            let end = clause_end(tail);
+            if end == 0 {
+                return Err(error());
+            }
+            // zone names are case sensitive
+            let start = words.len() - tail.len();
+            let until = original[start..start + end].join(" ");
+            let (mut resolved, named) = dates.resolve(&until, zone)?;
+            if !until.contains(':') {
+                let zone = named.unwrap_or(dates.zone());
+                let day = resolved.with_timezone(&zone).date_naive();
+                resolved = localize(day.and_hms_opt(23, 59, 59).ok_or_else(error)?, zone)?;
+            }
+            recurrence.until = Some(resolved);
+            rest = &tail[end..];
+        }
+
+        if let ["count", count, tail @ ..] = rest {
+            let count = count.parse::<u32>().map_err(|_| error())?;
+            if count == 0 {
+                return Err(error());
+            }
+            recurrence.count = Some(count);
+            rest = tail;
+        }
+
+        if !rest.is_empty() {
+            return Err(error());
+        }
+        Ok(recurrence)
+    }
+
+    /// Wall clock time of the occurrence after the one at `date`,
+    /// not minding `until` and `count`
+    pub fn next(&self, date: NaiveDateTime) -> Option<NaiveDateTime> {
+        let interval = self.interval as u64;
+        match self.frequency {
+            Frequency::Daily if self.weekdays.is_empty() => {
+                date.checked_add_days(Days::new(interval))
+            }
+            Frequency::Daily => (1..=7)
+                .filter_map(|step| date.checked_add_days(Days::new(step * interval)))
+                .find(|next| self.weekdays.contains(&next.weekday())),
+            Frequency::Weekly if self.weekdays.is_empty() => {
+                date.checked_add_days(Days::new(7 * interval))
+            }
+            Frequency::Weekly => {
+                let week = week_start(date);
+                (1..=7 * interval)
+                    .filter_map(|step| date.checked_add_days(Days::new(step)))
+                    .find(|next| {
+                        let weeks_apart = (week_start(*next) - week).num_days() / 7;
+                        (weeks_apart as u64).is_multiple_of(interval)
+                            && self.weekdays.contains(&next.weekday())
+                    })
+            }
+            Frequency::Monthly => self.next_month(date, self.interval),
+            Frequency::Yearly => self.next_month(date, self.interval.checked_mul(12)?),
+        }
+    }
+
+    /// The [Recurrence::day] (or that of `date`) `months` after `date`,
+    /// or the last day of that month when it is shorter
+    fn next_month(&self, date: NaiveDateTime, months: u32) -> Option<NaiveDateTime> {
+        let day = self.day.unwrap_or(date.day());
+        let month = date.with_day(1)?.checked_add_months(Months::new(months))?;
+        (1..=day).rev().find_map(|day| month.with_day(day))
+    }
+}
+
+/// `every 2 weeks on mon,fri until 2024-12-31 00:00 UTC count 10`, reads back with [Recurrence::parse]
+impl Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.interval {
+            1 => write!(f, "every {}", self.frequency.unit())?,
+            interval => write!(f, "every {} {}s", interval, self.frequency.unit())?,
+        }
+        if !self.weekdays.is_empty() {
+            let weekdays: Vec<String> = self
+                .weekdays
+                .iter()
+                .map(|day| day.to_string().to_lowercase())
+                .collect();
+            write!(f, " on {}", weekdays.join(","))?;
+        }
+        if let Some(until) = self.until {
+            let format = match until.second() {
+                0 => "%Y-%m-%d %H:%M",
+                _ => "%Y-%m-%d %H:%M:%S",
+            };
+            write!(f, " until {} UTC", until.format(format))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, " count {}", count)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_unit(unit: &str) -> Option<Frequency> {
+    match unit {
+        "day" | "days" => Some(Frequency::Daily),
+        "week" | "weeks" => Some(Frequency::Weekly),
+        "month" | "months" => Some(Frequency::Monthly),
+        "year" | "years" => Some(Frequency::Yearly),
+        _ => None,
+    }
+}
+
+/// Number of words before the next `until` or `count`
+fn clause_end(words: &[&str]) -> usize {
+    words
+        .iter()
+        .position(|word| matches!(*word, "until" | "count"))
+        .unwrap_or(words.len())
+}
+
+fn week_start(date: NaiveDateTime) -> NaiveDateTime {
+    date - Days::new(date.weekday().num_days_from_monday() as u64)
+}
@@ -0,0 +1,169 @@
+#![allow(dead_code)]
+use super::dates::DEFAULT_DISPLAY_FORMAT;
+use super::expression::{DateBucket, GroupBy, LeftVar};
+use super::task_model::{Priority, Status};
+use super::Task;
+use chrono::{DateTime, Datelike, Days};
+use chrono_tz::Tz;
+use std::fmt::{self, Display};
+
+/// Value of a single [Task] field
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Value {
+    Text(String),
+    /// In the zone of the task, or the default one
+    Date(DateTime<Tz>),
+    Status(Status),
+    Priority(Priority),
+    /// Sorted
+    Tags(Vec<String>),
+    Bool(bool),
+}
+
+impl Value {
+    /// Prints the value, dates in `date_format`
+    pub fn render(&self, date_format: &str) -> String {
+        match self {
+            Value::Text(text) => text.clone(),
+            Value::Date(date) => date.format(date_format).to_string(),
+            Value::Status(status) => status.to_string(),
+            Value::Priority(priority) => priority.to_string(),
+            Value::Tags(tags) => tags.join(", "),
+            Value::Bool(value) => value.to_string(),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(DEFAULT_DISPLAY_FORMAT))
+    }
+}
+
+/// A selected [Task] cut down to the requested columns
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    /// Id of the task, only kept for `select *`
+    pub id: Option<u64>,
+    pub values: Vec<(LeftVar, Value)>,
+}
+
+impl Row {
+    /// Dates of tasks without a zone of their own are shown in `default_zone`
+    pub fn project(task: &Task, columns: &[LeftVar], keep_id: bool, default_zone: Tz) -> Self {
+        Self {
+            id: keep_id.then_some(task.id),
+            values: columns
+                .iter()
+                .map(|column| (*column, task.get(*column, default_zone)))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, column: LeftVar) -> Option<&Value> {
+        self.values
+            .iter()
+            .find(|(field, _)| *field == column)
+            .map(|(_, value)| value)
+    }
+
+    /// `#1 name: qwe, status: false`, dates printed in `date_format`
+    pub fn render(&self, date_format: &str) -> String {
+        let values: Vec<String> = self
+            .values
+            .iter()
+            .map(|(column, value)| format!("{}: {}", column.name(), value.render(date_format)))
+            .collect();
+
+        match self.id {
+            Some(id) => format!("#{} {}", id, values.join(", ")),
+            None => values.join(", "),
+        }
+    }
+}
+
+impl Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(DEFAULT_DISPLAY_FORMAT))
+    }
+}
+
+/// Number of tasks in a `group by` group, or in the whole selection without grouping
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateRow {
+    /// Label of the group, e.g. `work`, `2024-W18` or `2024-05`
+    pub group: Option<(GroupBy, String)>,
+    pub count: usize,
+}
+
+impl AggregateRow {
+    /// Counts the tasks per group, groups come in the natural order of their keys.
+    ///
+    /// Dates fall into the day, week or month they are on in the task's zone or `default_zone`.
+    pub fn count(tasks: &[Task], group_by: Option<GroupBy>, default_zone: Tz) -> Vec<AggregateRow> {
+        let Some(group_by) = group_by else {
+            return vec![AggregateRow {
+                group: None,
+                count: tasks.len(),
+            }];
+        };
+
+        let mut groups: Vec<(Value, usize)> = Vec::new();
+        for task in tasks {
+            let key = group_key(task, group_by, default_zone);
+            match groups.iter_mut().find(|(group, _)| *group == key) {
+                Some((_, count)) => *count += 1,
+                None => groups.push((key, 1)),
+            }
+        }
+        groups.sort_by(|(one, _), (other, _)| one.partial_cmp(other).unwrap());
+
+        groups
+            .into_iter()
+            .map(|(key, count)| AggregateRow {
+                group: Some((group_by, key.to_string())),
+                count,
+            })
+            .collect()
+    }
+}
+
+/// `category: work, count: 3`
+impl Display for AggregateRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.group {
+            Some((GroupBy::Field(field), label)) => write!(f, "{}: {}, ", field.name(), label)?,
+            Some((GroupBy::Date(_), label)) => write!(f, "date: {}, ", label)?,
+            None => {}
+        }
+        write!(f, "count: {}", self.count)
+    }
+}
+
+/// Result of a `select`: the selected rows or the counts for `select count(*)`
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryResult {
+    Rows(Vec<Row>),
+    Counts(Vec<AggregateRow>),
+}
+
+/// Value the task is grouped by, dates by the label of their bucket: `2024-05-01`, `2024-W18`, `2024-05`.
+///
+/// The labels sort chronologically.
+fn group_key(task: &Task, group_by: GroupBy, default_zone: Tz) -> Value {
+    match group_by {
+        GroupBy::Field(field) => task.get(field, default_zone),
+        GroupBy::Date(bucket) => {
+            let day = task.local_date(default_zone).date_naive();
+            let label = match bucket {
+                DateBucket::Day => day.format("%Y-%m-%d"),
+                DateBucket::Week => day
+                    .checked_sub_days(Days::new(day.weekday().num_days_from_monday() as u64))
+                    .unwrap_or(day)
+                    .format("%G-W%V"),
+                DateBucket::Month => day.format("%Y-%m"),
+            };
+            Value::Text(label.to_string())
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use super::{read_document, write_document, MemoryStorage, Storage, TaskDocument};
+use crate::back::expression::Expr;
+use crate::back::{Task, TaskMgrError};
+use std::path::{Path, PathBuf};
+
+/// Keeps the tasks in memory and writes the whole list to a JSON document after every change
+#[derive(Debug)]
+pub struct JsonFileStorage {
+    path: PathBuf,
+    inner: MemoryStorage,
+}
+
+impl JsonFileStorage {
+    /// Opens the JSON document at `path`.
+    ///
+    /// A missing file is treated as an empty task list, it will be created on the first change.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, TaskMgrError> {
+        let path = path.as_ref().to_path_buf();
+        let document = read_document(&path)?;
+
+        Ok(Self {
+            path,
+            inner: MemoryStorage::from_tasks(document.tasks, document.next_id),
+        })
+    }
+
+    fn save(&self) -> Result<(), TaskMgrError> {
+        let document = TaskDocument {
+            seq: 0,
+            next_id: self.inner.next_id(),
+            tasks: self.inner.tasks().to_vec(),
+        };
+        write_document(&self.path, &document)
+    }
+}
+
+impl Storage for JsonFileStorage {
+    fn list(&self) -> Result<Vec<Task>, TaskMgrError> {
+        self.inner.list()
+    }
+
+    fn find(&self, id: u64) -> Result<Option<Task>, TaskMgrError> {
+        self.inner.find(id)
+    }
+
+    fn select(&self, filter: &Expr) -> Result<Vec<Task>, TaskMgrError> {
+        self.inner.select(filter)
+    }
+
+    fn insert(&mut self, task: Task) -> Result<u64, TaskMgrError> {
+        let id = self.inner.insert(task)?;
+        self.save()?;
+        Ok(id)
+    }
+
+    fn update(&mut self, task: Task) -> Result<(), TaskMgrError> {
+        self.inner.update(task)?;
+        self.save()
+    }
+
+    fn remove(&mut self, id: u64) -> Result<Task, TaskMgrError> {
+        let removed = self.inner.remove(id)?;
+        self.save()?;
+        Ok(removed)
+    }
+}
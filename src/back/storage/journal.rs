@@ -0,0 +1,242 @@
+#![allow(dead_code)]
+use super::{io_error, read_document, write_document, MemoryStorage, Storage, TaskDocument};
+use crate::back::expression::Expr;
+use crate::back::{StorageError, Task, TaskMgrError};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// How many journal records are written before they are folded into a new snapshot
+const DEFAULT_COMPACT_EVERY: u64 = 100;
+
+/// Keeps the tasks in memory and appends every change to a journal file,
+/// periodically compacting the journal into a JSON snapshot.
+///
+/// Every journal line is `<crc32> <json record>\n` and is synced before the change is applied,
+/// so a killed process loses nothing but the record it was in the middle of writing.
+/// Such a torn trailing record fails its checksum and is cut off on the next [JournalStorage::open].
+#[derive(Debug)]
+pub struct JournalStorage {
+    snapshot_path: PathBuf,
+    journal_path: PathBuf,
+    journal: File,
+    inner: MemoryStorage,
+    /// Sequence number of the last written record
+    seq: u64,
+    /// Sequence number of the last record already contained in the snapshot
+    snapshot_seq: u64,
+    compact_every: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JournalRecord {
+    seq: u64,
+    entry: JournalEntry,
+}
+
+/// A single mutating operation on the task list
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum JournalEntry {
+    Insert { task: Task },
+    Update { task: Task },
+    Remove { id: u64 },
+}
+
+impl JournalStorage {
+    /// Opens the snapshot at `snapshot_path` and replays the journal next to it
+    /// (same path with the `journal` extension) on top.
+    ///
+    /// Missing files are treated as an empty task list.
+    pub fn open(snapshot_path: impl AsRef<Path>) -> Result<Self, TaskMgrError> {
+        let snapshot_path = snapshot_path.as_ref().to_path_buf();
+        let journal_path = snapshot_path.with_extension("journal");
+
+        let document = read_document(&snapshot_path)?;
+        let mut inner = MemoryStorage::from_tasks(document.tasks, document.next_id);
+        let mut seq = document.seq;
+
+        let contents = match fs::read(&journal_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(io_error(e)),
+        };
+
+        let mut valid_len = 0;
+        for (index, line) in contents.split_inclusive(|byte| *byte == b'\n').enumerate() {
+            let is_last = valid_len + line.len() == contents.len();
+            let Some(record) = decode_record(line) else {
+                if is_last {
+                    break;
+                }
+                return Err(TaskMgrError::StorageErrorProp(StorageError::FormatError(
+                    format!("corrupted journal record at line {}", index + 1),
+                )));
+            };
+
+            // records written before a compaction that was interrupted
+            // between writing the snapshot and truncating the journal
+            if record.seq > seq {
+                apply(&mut inner, record.entry)?;
+                seq = record.seq;
+            }
+            valid_len += line.len();
+        }
+
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal_path)
+            .map_err(io_error)?;
+
+        if valid_len < contents.len() {
+            journal
+                .set_len(valid_len as u64)
+                .and_then(|_| journal.sync_all())
+                .map_err(io_error)?;
+        }
+
+        Ok(Self {
+            snapshot_path,
+            journal_path,
+            journal,
+            inner,
+            seq,
+            snapshot_seq: document.seq,
+            compact_every: DEFAULT_COMPACT_EVERY,
+        })
+    }
+
+    /// Sets how many journal records are written between automatic compactions
+    pub fn with_compaction_interval(mut self, records: u64) -> Self {
+        self.compact_every = records.max(1);
+        self
+    }
+
+    pub fn journal_path(&self) -> &Path {
+        &self.journal_path
+    }
+
+    /// Writes the current task list as a new snapshot and empties the journal
+    pub fn compact(&mut self) -> Result<(), TaskMgrError> {
+        let document = TaskDocument {
+            seq: self.seq,
+            next_id: self.inner.next_id(),
+            tasks: self.inner.tasks().to_vec(),
+        };
+        write_document(&self.snapshot_path, &document)?;
+
+        self.journal
+            .set_len(0)
+            .and_then(|_| self.journal.sync_all())
+            .map_err(io_error)?;
+        self.snapshot_seq = self.seq;
+
+        Ok(())
+    }
+
+    /// Durably appends `entry` to the journal, compacting it when it grew long enough
+    fn append(&mut self, entry: JournalEntry) -> Result<(), TaskMgrError> {
+        let record = JournalRecord {
+            seq: self.seq + 1,
+            entry,
+        };
+        let json = serde_json::to_string(&record).map_err(|e| {
+            TaskMgrError::StorageErrorProp(StorageError::FormatError(e.to_string()))
+        })?;
+        let line = format!("{:08x} {}\n", crc32(json.as_bytes()), json);
+
+        self.journal
+            .write_all(line.as_bytes())
+            .and_then(|_| self.journal.sync_data())
+            .map_err(io_error)?;
+        self.seq = record.seq;
+
+        apply(&mut self.inner, record.entry)?;
+
+        if self.seq - self.snapshot_seq >= self.compact_every {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Storage for JournalStorage {
+    fn list(&self) -> Result<Vec<Task>, TaskMgrError> {
+        self.inner.list()
+    }
+
+    fn find(&self, id: u64) -> Result<Option<Task>, TaskMgrError> {
+        self.inner.find(id)
+    }
+
+    fn select(&self, filter: &Expr) -> Result<Vec<Task>, TaskMgrError> {
+        self.inner.select(filter)
+    }
+
+    /// The id is assigned before the record is written,
+    /// so replaying the journal hands out the very same ids
+    fn insert(&mut self, mut task: Task) -> Result<u64, TaskMgrError> {
+        if task.id == 0 {
+            task.id = self.inner.next_id();
+        }
+        let id = task.id;
+        self.append(JournalEntry::Insert { task })?;
+        Ok(id)
+    }
+
+    fn update(&mut self, task: Task) -> Result<(), TaskMgrError> {
+        self.inner
+            .find(task.id)?
+            .ok_or_else(|| TaskMgrError::TaskNotFound(format!("#{}", task.id)))?;
+        self.append(JournalEntry::Update { task })
+    }
+
+    fn remove(&mut self, id: u64) -> Result<Task, TaskMgrError> {
+        let removed = self
+            .inner
+            .find(id)?
+            .ok_or_else(|| TaskMgrError::TaskNotFound(format!("#{}", id)))?;
+        self.append(JournalEntry::Remove { id })?;
+        Ok(removed)
+    }
+}
+
+fn apply(storage: &mut MemoryStorage, entry: JournalEntry) -> Result<(), TaskMgrError> {
+    match entry {
+        JournalEntry::Insert { task } => storage.insert(task).map(|_| ()),
+        JournalEntry::Update { task } => storage.update(task),
+        JournalEntry::Remove { id } => storage.remove(id).map(|_| ()),
+    }
+}
+
+/// Parses a `<crc32> <json>\n` journal line
+///
+/// Returns [None] for incomplete lines and lines that fail the checksum.
+fn decode_record(line: &[u8]) -> Option<JournalRecord> {
+    let line = line.strip_suffix(b"\n")?;
+    let line = std::str::from_utf8(line).ok()?;
+    let (checksum, json) = line.split_once(' ')?;
+    let checksum = u32::from_str_radix(checksum, 16).ok()?;
+
+    if checksum != crc32(json.as_bytes()) {
+        return None;
+    }
+
+    serde_json::from_str(json).ok()
+}
+
+/// Plain bitwise CRC-32 (IEEE), the journal records are small enough to not need a lookup table
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
@@ -0,0 +1,99 @@
+use super::Storage;
+use crate::back::expression::Expr;
+use crate::back::{Task, TaskMgrError};
+
+/// Keeps the tasks in a plain [Vec], nothing survives the process
+#[derive(Debug)]
+pub struct MemoryStorage {
+    tasks: Vec<Task>,
+    next_id: u64,
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self {
+            tasks: Vec::new(),
+            next_id: 1,
+        }
+    }
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes over previously stored tasks, `next_id` is the id the next new task should get.
+    ///
+    /// Tasks stored before they had ids get fresh ones.
+    pub fn from_tasks(tasks: Vec<Task>, next_id: u64) -> Self {
+        let max_id = tasks.iter().map(|task| task.id).max().unwrap_or(0);
+        let mut storage = Self {
+            tasks: Vec::with_capacity(tasks.len()),
+            next_id: next_id.max(max_id + 1),
+        };
+        for task in tasks {
+            storage.push(task);
+        }
+        storage
+    }
+
+    pub fn tasks(&self) -> &[Task] {
+        &self.tasks
+    }
+
+    pub fn next_id(&self) -> u64 {
+        self.next_id
+    }
+
+    fn push(&mut self, mut task: Task) -> u64 {
+        if task.id == 0 {
+            task.id = self.next_id;
+        }
+        self.next_id = self.next_id.max(task.id + 1);
+
+        // ids only grow, so this appends unless a removed task is put back
+        let id = task.id;
+        let index = self.tasks.partition_point(|task| task.id < id);
+        self.tasks.insert(index, task);
+        id
+    }
+
+    fn position(&self, id: u64) -> Result<usize, TaskMgrError> {
+        self.tasks
+            .iter()
+            .position(|task| task.id == id)
+            .ok_or_else(|| TaskMgrError::TaskNotFound(format!("#{}", id)))
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn list(&self) -> Result<Vec<Task>, TaskMgrError> {
+        Ok(self.tasks.clone())
+    }
+
+    fn find(&self, id: u64) -> Result<Option<Task>, TaskMgrError> {
+        Ok(self.tasks.iter().find(|task| task.id == id).cloned())
+    }
+
+    fn insert(&mut self, task: Task) -> Result<u64, TaskMgrError> {
+        Ok(self.push(task))
+    }
+
+    fn update(&mut self, task: Task) -> Result<(), TaskMgrError> {
+        let index = self.position(task.id)?;
+        self.tasks[index] = task;
+        Ok(())
+    }
+
+    fn remove(&mut self, id: u64) -> Result<Task, TaskMgrError> {
+        let index = self.position(id)?;
+        Ok(self.tasks.remove(index))
+    }
+
+    /// Only clones the tasks that made it through the filters,
+    /// unless they are on [Task::is_blocked] which needs all of them
+    fn select(&self, filter: &Expr) -> Result<Vec<Task>, TaskMgrError> {
+        super::select_from(&self.tasks, filter)
+    }
+}
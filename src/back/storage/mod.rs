@@ -0,0 +1,145 @@
+mod file;
+mod journal;
+mod memory;
+mod sqlite;
+pub use file::JsonFileStorage;
+pub use journal::JournalStorage;
+pub use memory::MemoryStorage;
+pub use sqlite::SqliteStorage;
+
+use super::dependencies::Dependencies;
+use super::expression::{Expr, LeftVar};
+use super::{StorageError, Task, TaskMgrError};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+/// Backend holding the tasks of a [TaskManager](crate::back::task_manager::TaskManager).
+///
+/// Tasks are addressed by their [Task::id], which the backend assigns on insertion
+/// and never hands out again, even after the task is removed.
+pub trait Storage {
+    /// Returns all of the stored tasks in insertion order
+    fn list(&self) -> Result<Vec<Task>, TaskMgrError>;
+
+    fn find(&self, id: u64) -> Result<Option<Task>, TaskMgrError>;
+
+    /// Stores the task and returns its id
+    ///
+    /// A task without an id (`0`) gets the next free one, otherwise its id is kept as is.
+    fn insert(&mut self, task: Task) -> Result<u64, TaskMgrError>;
+
+    /// Replaces the task with the same [Task::id]
+    ///
+    /// Or returns [TaskMgrError::TaskNotFound] if there is no such task.
+    fn update(&mut self, task: Task) -> Result<(), TaskMgrError>;
+
+    /// Removes the task with the given id and hands it back
+    ///
+    /// Or returns [TaskMgrError::TaskNotFound] if there is no such task.
+    fn remove(&mut self, id: u64) -> Result<Task, TaskMgrError>;
+
+    /// Returns the tasks matching the filter, in insertion order
+    ///
+    /// Filters the whole [Storage::list] by default,
+    /// backends able to evaluate the filter themselves should override it.
+    /// Filters on [Task::is_blocked] have to look at the tasks waited on as well.
+    fn select(&self, filter: &Expr) -> Result<Vec<Task>, TaskMgrError> {
+        select_from(&self.list()?, filter)
+    }
+}
+
+/// Clones the `tasks` matching the filter, all of them are needed to tell [Task::is_blocked]
+fn select_from(tasks: &[Task], filter: &Expr) -> Result<Vec<Task>, TaskMgrError> {
+    let marked;
+    let tasks = if filter.mentions(LeftVar::IsBlocked) {
+        let mut tasks = tasks.to_vec();
+        Dependencies::new(tasks.clone()).mark_blocked(&mut tasks);
+        marked = tasks;
+        &marked
+    } else {
+        tasks
+    };
+
+    let mut selected = Vec::new();
+    for task in tasks {
+        if task
+            .matches(filter)
+            .map_err(TaskMgrError::GeneralTaskError)?
+        {
+            selected.push(task.clone());
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Lets the backend be picked at runtime, e.g. `TaskManager<Box<dyn Storage>>`
+impl<S: Storage + ?Sized> Storage for Box<S> {
+    fn list(&self) -> Result<Vec<Task>, TaskMgrError> {
+        (**self).list()
+    }
+
+    fn find(&self, id: u64) -> Result<Option<Task>, TaskMgrError> {
+        (**self).find(id)
+    }
+
+    fn insert(&mut self, task: Task) -> Result<u64, TaskMgrError> {
+        (**self).insert(task)
+    }
+
+    fn update(&mut self, task: Task) -> Result<(), TaskMgrError> {
+        (**self).update(task)
+    }
+
+    fn remove(&mut self, id: u64) -> Result<Task, TaskMgrError> {
+        (**self).remove(id)
+    }
+
+    fn select(&self, filter: &Expr) -> Result<Vec<Task>, TaskMgrError> {
+        (**self).select(filter)
+    }
+}
+
+/// On-disk layout of a task list snapshot
+#[derive(Serialize, Deserialize, Default)]
+struct TaskDocument {
+    /// Sequence number of the last journal record folded into this snapshot
+    #[serde(default)]
+    seq: u64,
+    /// Id the next inserted task gets, `0` for documents written before tasks had ids
+    #[serde(default)]
+    next_id: u64,
+    tasks: Vec<Task>,
+}
+
+fn io_error(e: std::io::Error) -> TaskMgrError {
+    TaskMgrError::StorageErrorProp(StorageError::IoError(e.to_string()))
+}
+
+fn format_error(e: serde_json::Error) -> TaskMgrError {
+    TaskMgrError::StorageErrorProp(StorageError::FormatError(e.to_string()))
+}
+
+/// Reads the document at `path`, a missing file is treated as an empty task list
+fn read_document(path: &Path) -> Result<TaskDocument, TaskMgrError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(format_error),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(TaskDocument::default()),
+        Err(e) => Err(io_error(e)),
+    }
+}
+
+/// Writes the document next to `path` first and then renames it over the target,
+/// so an interrupted save never leaves a half-written list behind.
+fn write_document(path: &Path, document: &TaskDocument) -> Result<(), TaskMgrError> {
+    let json = serde_json::to_string_pretty(document).map_err(format_error)?;
+
+    let tmp_path = path.with_extension("tmp");
+    let mut tmp = File::create(&tmp_path).map_err(io_error)?;
+    tmp.write_all(json.as_bytes())
+        .and_then(|_| tmp.sync_all())
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .map_err(io_error)
+}
@@ -0,0 +1,485 @@
+#![allow(dead_code)]
+use super::Storage;
+use crate::back::expression::{split_tags, Expr, LeftVar, Op};
+use crate::back::task_model::{parse_task_date, Priority, Status};
+use crate::back::{StorageError, Task, TaskMgrError};
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, Row};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Dates are stored as UTC text in this format, so comparing the text compares them chronologically.
+///
+/// It is also what [NaiveDateTime]'s `to_string()` produces, which keeps `like` on dates
+/// behaving the same as in the in-memory backends.
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// `'true'` when one of the tasks in `blocked_by` is neither done nor cancelled,
+/// mirroring [Task::is_blocked]
+const IS_BLOCKED: &str = "(CASE WHEN EXISTS (
+        SELECT 1 FROM json_each(tasks.blocked_by) AS waited
+        JOIN tasks AS blocker ON blocker.id = waited.value
+        WHERE blocker.status NOT IN ('done', 'cancelled')
+    ) THEN 'true' ELSE 'false' END)";
+
+const COLUMNS: &str =
+    "id, name, description, date, category, status, zone, recurrence, priority, tags, parent,
+    blocked_by";
+
+/// Keeps the tasks in an SQLite database and runs the `select` filters as SQL
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens (or creates) the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, TaskMgrError> {
+        Self::init(Connection::open(path).map_err(db_error)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, TaskMgrError> {
+        Self::init(Connection::open_in_memory().map_err(db_error)?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, TaskMgrError> {
+        // `like` is a case sensitive substring match everywhere else
+        connection
+            .execute_batch(
+                "PRAGMA case_sensitive_like = ON;
+                -- AUTOINCREMENT keeps ids of removed tasks from being handed out again
+                CREATE TABLE IF NOT EXISTS tasks (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    description TEXT NOT NULL,
+                    date TEXT NOT NULL,
+                    category TEXT NOT NULL,
+                    -- kept in step with status for databases read by older versions
+                    done INTEGER NOT NULL,
+                    zone TEXT,
+                    -- the Recurrence as JSON
+                    recurrence TEXT,
+                    status TEXT,
+                    priority TEXT NOT NULL DEFAULT 'normal',
+                    -- JSON array of the tags
+                    tags TEXT NOT NULL DEFAULT '[]',
+                    -- id of the task this one is a subtask of
+                    parent INTEGER,
+                    -- JSON array of the ids of the tasks this one waits on
+                    blocked_by TEXT NOT NULL DEFAULT '[]'
+                );
+                CREATE INDEX IF NOT EXISTS tasks_name ON tasks (name);",
+            )
+            .map_err(db_error)?;
+
+        // databases created before the column existed
+        add_missing_column(&connection, "zone", "TEXT")?;
+        add_missing_column(&connection, "recurrence", "TEXT")?;
+        add_missing_column(&connection, "status", "TEXT")?;
+        add_missing_column(&connection, "priority", "TEXT NOT NULL DEFAULT 'normal'")?;
+        add_missing_column(&connection, "tags", "TEXT NOT NULL DEFAULT '[]'")?;
+        add_missing_column(&connection, "parent", "INTEGER")?;
+        add_missing_column(&connection, "blocked_by", "TEXT NOT NULL DEFAULT '[]'")?;
+        connection
+            .execute(
+                "UPDATE tasks SET status = CASE done WHEN 0 THEN 'todo' ELSE 'done' END
+                WHERE status IS NULL",
+                [],
+            )
+            .map_err(db_error)?;
+
+        Ok(Self { connection })
+    }
+
+    fn query(&self, sql: &str, values: Vec<Value>) -> Result<Vec<Task>, TaskMgrError> {
+        let mut statement = self.connection.prepare(sql).map_err(db_error)?;
+        let rows = statement
+            .query_map(params_from_iter(values), task_from_row)
+            .map_err(db_error)?;
+
+        rows.collect::<Result<Vec<Task>, _>>().map_err(db_error)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn list(&self) -> Result<Vec<Task>, TaskMgrError> {
+        self.query(
+            &format!("SELECT {} FROM tasks ORDER BY id", COLUMNS),
+            Vec::new(),
+        )
+    }
+
+    fn find(&self, id: u64) -> Result<Option<Task>, TaskMgrError> {
+        let sql = format!("SELECT {} FROM tasks WHERE id = ?1", COLUMNS);
+        let found = self.query(&sql, vec![Value::Integer(id as i64)])?;
+        Ok(found.into_iter().next())
+    }
+
+    fn insert(&mut self, task: Task) -> Result<u64, TaskMgrError> {
+        // NULL lets SQLite pick the next id
+        let id = (task.id != 0).then_some(task.id as i64);
+        self.connection
+            .execute(
+                &format!(
+                    "INSERT INTO tasks ({}, done)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    COLUMNS
+                ),
+                params![
+                    id,
+                    task.name,
+                    task.description,
+                    task.date.format(DATE_FORMAT).to_string(),
+                    task.category,
+                    task.status.name(),
+                    task.zone.map(|zone| zone.name()),
+                    recurrence_json(&task)?,
+                    task.priority.name(),
+                    tags_json(&task)?,
+                    task.parent.map(|parent| parent as i64),
+                    blocked_by_json(&task)?,
+                    task.status == Status::Done
+                ],
+            )
+            .map_err(db_error)?;
+        Ok(self.connection.last_insert_rowid() as u64)
+    }
+
+    fn update(&mut self, task: Task) -> Result<(), TaskMgrError> {
+        let changed = self
+            .connection
+            .execute(
+                "UPDATE tasks SET name = ?1, description = ?2, date = ?3, category = ?4, status = ?5,
+                zone = ?6, recurrence = ?7, priority = ?8, tags = ?9, parent = ?10,
+                blocked_by = ?11, done = ?12 WHERE id = ?13",
+                params![
+                    task.name,
+                    task.description,
+                    task.date.format(DATE_FORMAT).to_string(),
+                    task.category,
+                    task.status.name(),
+                    task.zone.map(|zone| zone.name()),
+                    recurrence_json(&task)?,
+                    task.priority.name(),
+                    tags_json(&task)?,
+                    task.parent.map(|parent| parent as i64),
+                    blocked_by_json(&task)?,
+                    task.status == Status::Done,
+                    task.id as i64
+                ],
+            )
+            .map_err(db_error)?;
+
+        if changed == 0 {
+            return Err(TaskMgrError::TaskNotFound(format!("#{}", task.id)));
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, id: u64) -> Result<Task, TaskMgrError> {
+        let task = self
+            .find(id)?
+            .ok_or_else(|| TaskMgrError::TaskNotFound(format!("#{}", id)))?;
+        self.connection
+            .execute("DELETE FROM tasks WHERE id = ?1", params![id as i64])
+            .map_err(db_error)?;
+        Ok(task)
+    }
+
+    fn select(&self, filter: &Expr) -> Result<Vec<Task>, TaskMgrError> {
+        let mut values = Vec::new();
+        let clause = compile_filter(filter, &mut values)?;
+        let sql = format!("SELECT {} FROM tasks WHERE {} ORDER BY id", COLUMNS, clause);
+        self.query(&sql, values)
+    }
+}
+
+/// Translates the parsed filter into a parameterized `WHERE` clause,
+/// pushing the values to bind into `values`
+fn compile_filter(filter: &Expr, values: &mut Vec<Value>) -> Result<String, TaskMgrError> {
+    match filter {
+        Expr::And(left, right) => Ok(format!(
+            "({} AND {})",
+            compile_filter(left, values)?,
+            compile_filter(right, values)?
+        )),
+        Expr::Or(left, right) => Ok(format!(
+            "({} OR {})",
+            compile_filter(left, values)?,
+            compile_filter(right, values)?
+        )),
+        Expr::Not(inner) => Ok(format!("(NOT {})", compile_filter(inner, values)?)),
+        Expr::Comparison(leftvar, op, other) => compile_comparison(leftvar, op, other, values),
+    }
+}
+
+fn compile_comparison(
+    leftvar: &LeftVar,
+    op: &Op,
+    other: &str,
+    values: &mut Vec<Value>,
+) -> Result<String, TaskMgrError> {
+    match leftvar {
+        LeftVar::Status => return Ok(compile_status(op, other, values)),
+        LeftVar::Priority => {
+            let names = Priority::ALL.map(|priority| priority.name());
+            let rank = Priority::parse(other).map(|priority| priority as usize);
+            return Ok(compile_ranked("priority", &names, rank, op, other, values));
+        }
+        LeftVar::Tags => return Ok(compile_tags(op, other, values)),
+        _ => {}
+    }
+
+    let column = match leftvar {
+        LeftVar::Name => "name",
+        LeftVar::Description => "description",
+        LeftVar::Date => "date",
+        LeftVar::Category => "category",
+        LeftVar::Status => "status",
+        LeftVar::Priority => "priority",
+        LeftVar::Tags => "tags",
+        LeftVar::IsBlocked => IS_BLOCKED,
+    };
+
+    let value = match leftvar {
+        LeftVar::Date => parse_task_date(other.to_string())
+            .map_err(TaskMgrError::GeneralTaskError)?
+            .format(DATE_FORMAT)
+            .to_string(),
+        _ => other.to_string(),
+    };
+
+    let clause = match op {
+        Op::Like => {
+            values.push(Value::Text(format!("%{}%", escape_like(&value))));
+            format!("{} LIKE ?{} ESCAPE '\\'", column, values.len())
+        }
+        _ => {
+            values.push(Value::Text(value));
+            format!("{} {} ?{}", column, sql_op(op), values.len())
+        }
+    };
+
+    Ok(clause)
+}
+
+/// Mirrors [Task::matches](crate::back::Task::matches): `true` and `false` ask whether the task
+/// is done, status names compare in lifecycle order, anything else compares the name as text
+fn compile_status(op: &Op, other: &str, values: &mut Vec<Value>) -> String {
+    if matches!(other, "true" | "false") {
+        values.push(Value::Text(other.to_string()));
+        return format!(
+            "(CASE status WHEN 'done' THEN 'true' ELSE 'false' END) {} ?{}",
+            sql_op(op),
+            values.len()
+        );
+    }
+
+    let names = Status::ALL.map(|status| status.name());
+    let rank = Status::from_name(other).map(|status| status as usize);
+    compile_ranked("status", &names, rank, op, other, values)
+}
+
+/// Compares a column holding one of `names` by its position among them when the value is
+/// one of the names too, `rank` being its position, or as text otherwise
+fn compile_ranked(
+    column: &str,
+    names: &[&str],
+    rank: Option<usize>,
+    op: &Op,
+    other: &str,
+    values: &mut Vec<Value>,
+) -> String {
+    match (op, rank) {
+        (Op::Like, _) => {
+            values.push(Value::Text(format!("%{}%", escape_like(other))));
+            format!("{} LIKE ?{} ESCAPE '\\'", column, values.len())
+        }
+        (_, Some(rank)) => {
+            let ranks: Vec<String> = names
+                .iter()
+                .enumerate()
+                .map(|(rank, name)| format!("WHEN '{}' THEN {}", name, rank))
+                .collect();
+            values.push(Value::Integer(rank as i64));
+            format!(
+                "(CASE {} {} END) {} ?{}",
+                column,
+                ranks.join(" "),
+                sql_op(op),
+                values.len()
+            )
+        }
+        (_, None) => {
+            values.push(Value::Text(other.to_string()));
+            format!("{} {} ?{}", column, sql_op(op), values.len())
+        }
+    }
+}
+
+/// Looks the tags up in the JSON array the column holds,
+/// mirroring [Task::matches](crate::back::Task::matches)
+fn compile_tags(op: &Op, other: &str, values: &mut Vec<Value>) -> String {
+    let tags: BTreeSet<String> = match op {
+        Op::Has => BTreeSet::from([other.to_string()]),
+        _ => split_tags(other).collect(),
+    };
+    if tags.is_empty() {
+        // a task has none of no tags and all of them
+        return if *op == Op::All { "1" } else { "0" }.to_string();
+    }
+
+    let placeholders: Vec<String> = tags
+        .iter()
+        .map(|tag| {
+            values.push(Value::Text(tag.clone()));
+            format!("?{}", values.len())
+        })
+        .collect();
+    let found = format!(
+        "(SELECT COUNT(DISTINCT value) FROM json_each(tags) WHERE value IN ({}))",
+        placeholders.join(", ")
+    );
+
+    match op {
+        Op::All => format!("{} = {}", found, tags.len()),
+        _ => format!("{} > 0", found),
+    }
+}
+
+fn sql_op(op: &Op) -> &'static str {
+    match op {
+        Op::Equals => "=",
+        Op::NotEquals => "<>",
+        Op::Greater => ">",
+        Op::Less => "<",
+        Op::GrEquals => ">=",
+        Op::LeEquals => "<=",
+        Op::Like => "LIKE",
+        // tags are compiled by compile_tags
+        Op::Has | Op::Any | Op::All => "IN",
+    }
+}
+
+/// Escapes the `LIKE` wildcards so the value is matched literally
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn task_from_row(row: &Row) -> rusqlite::Result<Task> {
+    let date: String = row.get(3)?;
+    let date = NaiveDateTime::parse_from_str(&date, DATE_FORMAT).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+    let id: i64 = row.get(0)?;
+    let zone: Option<String> = row.get(6)?;
+    let zone = zone
+        .map(|zone| zone.parse::<Tz>())
+        .transpose()
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(6, rusqlite::types::Type::Text, e.into())
+        })?;
+
+    let status: String = row.get(5)?;
+    let priority: String = row.get(8)?;
+
+    let tags: String = row.get(9)?;
+    let tags = serde_json::from_str(&tags).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(9, rusqlite::types::Type::Text, e.into())
+    })?;
+
+    let parent: Option<i64> = row.get(10)?;
+
+    let blocked_by: String = row.get(11)?;
+    let blocked_by = serde_json::from_str(&blocked_by).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(11, rusqlite::types::Type::Text, e.into())
+    })?;
+
+    let recurrence: Option<String> = row.get(7)?;
+    let recurrence = recurrence
+        .map(|recurrence| serde_json::from_str(&recurrence))
+        .transpose()
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(7, rusqlite::types::Type::Text, e.into())
+        })?;
+
+    Ok(Task {
+        id: id as u64,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        date: date.and_utc(),
+        zone,
+        category: row.get(4)?,
+        status: Status::from_name(&status).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                5,
+                rusqlite::types::Type::Text,
+                format!("unknown status {}", status).into(),
+            )
+        })?,
+        priority: Priority::parse(&priority).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                8,
+                rusqlite::types::Type::Text,
+                format!("unknown priority {}", priority).into(),
+            )
+        })?,
+        tags,
+        parent: parent.map(|parent| parent as u64),
+        blocked_by,
+        is_blocked: false,
+        recurrence,
+    })
+}
+
+fn tags_json(task: &Task) -> Result<String, TaskMgrError> {
+    serde_json::to_string(&task.tags)
+        .map_err(|e| TaskMgrError::StorageErrorProp(StorageError::FormatError(e.to_string())))
+}
+
+fn blocked_by_json(task: &Task) -> Result<String, TaskMgrError> {
+    serde_json::to_string(&task.blocked_by)
+        .map_err(|e| TaskMgrError::StorageErrorProp(StorageError::FormatError(e.to_string())))
+}
+
+fn recurrence_json(task: &Task) -> Result<Option<String>, TaskMgrError> {
+    task.recurrence
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| TaskMgrError::StorageErrorProp(StorageError::FormatError(e.to_string())))
+}
+
+/// Adds `column` to the tasks table unless it is already there
+fn add_missing_column(
+    connection: &Connection,
+    column: &str,
+    definition: &str,
+) -> Result<(), TaskMgrError> {
+    let exists: bool = connection
+        .query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('tasks') WHERE name = ?1",
+            params![column],
+            |row| row.get(0),
+        )
+        .map_err(db_error)?;
+
+    if !exists {
+        connection
+            .execute_batch(&format!(
+                "ALTER TABLE tasks ADD COLUMN {} {}",
+                column, definition
+            ))
+            .map_err(db_error)?;
+    }
+    Ok(())
+}
+
+fn db_error(e: rusqlite::Error) -> TaskMgrError {
+    TaskMgrError::StorageErrorProp(StorageError::DatabaseError(e.to_string()))
+}
@@ -1,218 +1,885 @@
 #![allow(dead_code)]
-use super::expression::{command_equals, parse_args, LeftVar, Op};
-use super::task_model::parse_task_date;
+use super::dates::DateParser;
+use super::dependencies::Dependencies;
+use super::export::ExportFormat;
+use super::expression::{
+    command_equals, parse_query_with, Expr, LeftVar, Op, Projection, Query, SortOrder,
+};
+use super::history::{Change, History, Revision};
+use super::rows::{AggregateRow, QueryResult, Row};
+use super::storage::{JsonFileStorage, MemoryStorage, Storage};
+use super::task_model::{Priority, Status};
+use super::tree::TaskTree;
 use super::{Task, TaskMgrError};
-use std::fmt::Display;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::path::Path;
 
+/// Executes the commands against tasks held by any [Storage] backend,
+/// in-memory by default
 #[derive(Debug)]
-pub struct TaskManager {
-    tasks: Vec<Task>,
+pub struct TaskManager<S: Storage = MemoryStorage> {
+    storage: S,
+    dates: DateParser,
+    /// Whether a task can only be done once its subtasks are
+    strict_subtasks: bool,
+    history: History,
+    /// Writes of the command [TaskManager::exec_command] is running, [None] outside of it
+    changes: Option<Vec<Change>>,
+}
+
+/// What happens to the subtasks of a deleted task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnDelete {
+    /// They move up to the parent of the deleted task
+    #[default]
+    Reparent,
+    /// They are deleted along with it, at any depth
+    Cascade,
 }
 
 impl TaskManager {
     pub fn new() -> Self {
-        Self { tasks: Vec::new() }
+        Self::with_storage(MemoryStorage::new())
+    }
+}
+
+impl TaskManager<JsonFileStorage> {
+    /// Creates a [TaskManager] backed by the JSON document at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TaskMgrError> {
+        Ok(Self::with_storage(JsonFileStorage::open(path)?))
+    }
+}
+
+impl<S: Storage> TaskManager<S> {
+    pub fn with_storage(storage: S) -> Self {
+        Self {
+            storage,
+            dates: DateParser::default(),
+            strict_subtasks: false,
+            history: History::default(),
+            changes: None,
+        }
+    }
+
+    /// Replaces the parser resolving relative and natural-language dates,
+    /// e.g. to pin the clock in tests or to switch the locale
+    pub fn with_dates(mut self, dates: DateParser) -> Self {
+        self.dates = dates;
+        self
     }
 
-    pub fn get_tasks(&self) -> &Vec<Task> {
-        &self.tasks
+    /// Refuses to mark a task done while some of its subtasks are neither done nor cancelled
+    pub fn with_strict_subtasks(mut self, strict: bool) -> Self {
+        self.strict_subtasks = strict;
+        self
     }
+
+    /// Keeps up to `limit` commands to undo instead of
+    /// [DEFAULT_HISTORY_LIMIT](super::history::DEFAULT_HISTORY_LIMIT)
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history = History::new(limit);
+        self
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn get_tasks(&self) -> Result<Vec<Task>, TaskMgrError> {
+        self.storage.list()
+    }
+
     /// Matches the incoming text command in form of:
     ///
     /// `"add name, description, 2222-12-12 00:00, category"`
     ///
-    /// and decides what to do with it
+    /// and decides what to do with it.
+    ///
+    /// Commands working on an existing task take either its name or its id as `#id`.
+    ///
+    /// Whatever a command changes can be taken back with `undo` and brought back with `redo`,
+    /// `history` lists both.
     pub fn exec_command(&mut self, command: &str) -> Result<String, TaskMgrError> {
+        let command = command.trim_start();
+        if command.is_empty() {
+            return Err(TaskMgrError::EmptyCommand);
+        }
+
+        match command {
+            _ if command_equals(command, "undo").unwrap() => {
+                let revision = self.undo()?;
+                Ok(format!("Undone {}", revision))
+            }
+            _ if command_equals(command, "redo").unwrap() => {
+                let revision = self.redo()?;
+                Ok(format!("Redone {}", revision))
+            }
+            _ if command_equals(command, "history").unwrap() => {
+                let list = |revisions: Vec<&Revision>| -> Vec<String> {
+                    revisions
+                        .iter()
+                        .enumerate()
+                        .map(|(i, revision)| format!("{}. {}", i + 1, revision))
+                        .collect()
+                };
+                let mut lines = Vec::new();
+                let undoable = list(self.history.undoable().collect());
+                if !undoable.is_empty() {
+                    lines.push("Undo would revert:".to_string());
+                    lines.extend(undoable);
+                }
+                let redoable = list(self.history.redoable().collect());
+                if !redoable.is_empty() {
+                    lines.push("Redo would repeat:".to_string());
+                    lines.extend(redoable);
+                }
+
+                if lines.is_empty() {
+                    return Ok("Nothing to undo or redo".to_string());
+                }
+                Ok(lines.join("\n"))
+            }
+            _ => {
+                self.changes = Some(Vec::new());
+                let result = self.run_command(command);
+                let changes = self.changes.take().unwrap_or_default();
+                // a command failing half way still wrote what it wrote
+                if !changes.is_empty() {
+                    self.history.record(Revision {
+                        command: command.trim().to_string(),
+                        changes,
+                    });
+                }
+                result
+            }
+        }
+    }
+
+    /// Runs any command but `undo`, `redo` and `history`, see [TaskManager::exec_command]
+    fn run_command(&mut self, command: &str) -> Result<String, TaskMgrError> {
         match command {
             _ if command_equals(command, "add").unwrap() => {
-                let command = command.strip_prefix("add").unwrap().trim();
-                let task = Task::new(command);
+                let command = command_args(command);
+                // the options after the four fields may name other tasks
+                let fields = command
+                    .split(';')
+                    .enumerate()
+                    .map(|(i, field)| match i {
+                        0..=3 => Ok(field.to_string()),
+                        _ => self.resolve_task_refs(field),
+                    })
+                    .collect::<Result<Vec<String>, _>>()?;
+                let task = Task::new_with(&fields.join(";"), &self.dates);
                 if let Ok(t) = task {
-                    self.add(t).unwrap();
-                    return Ok("Task added".to_string());
+                    let mut added = format!("due {}", self.dates.format(t.date, t.zone));
+                    if t.priority != Priority::Normal {
+                        added.push_str(&format!(", priority {}", t.priority));
+                    }
+                    if !t.tags.is_empty() {
+                        let tags: Vec<&str> = t.tags.iter().map(String::as_str).collect();
+                        added.push_str(&format!(", tags {}", tags.join(", ")));
+                    }
+                    if let Some(rule) = &t.recurrence {
+                        added.push_str(&format!(", repeats {}", rule));
+                    }
+                    if let Some(parent) = t.parent {
+                        added.push_str(&format!(", subtask of #{}", parent));
+                    }
+                    if !t.blocked_by.is_empty() {
+                        let blockers: Vec<String> =
+                            t.blocked_by.iter().map(|id| format!("#{}", id)).collect();
+                        added.push_str(&format!(", after {}", blockers.join(", ")));
+                    }
+                    let id = self.add(t)?;
+                    return Ok(format!("Task added: #{}, {}", id, added));
                 }
                 Err(TaskMgrError::TaskCreationErrorProp(task.unwrap_err()))
             }
+            // delete task_ref[; cascade|reparent]
             _ if command_equals(command, "delete").unwrap() => {
-                let command = command.strip_prefix("delete").unwrap().trim();
-                let deleted = self.delete(command);
-                if let Ok(_) = deleted {
-                    return Ok(format!("Task deleted"));
+                let command = command_args(command);
+                let (task_ref, on_delete) = match command.split_once(';') {
+                    None => (command, OnDelete::Reparent),
+                    Some((task_ref, mode)) => match mode.trim() {
+                        "reparent" => (task_ref, OnDelete::Reparent),
+                        "cascade" => (task_ref, OnDelete::Cascade),
+                        _ => return Err(TaskMgrError::WrongQuery(command.to_string())),
+                    },
+                };
+
+                let subtasks = self.delete_with(task_ref, on_delete)?;
+                Ok(match (subtasks.len(), on_delete) {
+                    (0, _) => "Task deleted".to_string(),
+                    (n, OnDelete::Reparent) => format!("Task deleted, {} subtask(s) moved up", n),
+                    (n, OnDelete::Cascade) => format!("Task deleted along with {} subtask(s)", n),
+                })
+            }
+            // tree [task_ref]
+            _ if command_equals(command, "tree").unwrap() => {
+                let command = command_args(command);
+                let root = match command {
+                    "" => None,
+                    task_ref => Some(self.find(task_ref)?),
+                };
+
+                let lines = self.render_tree(root.as_ref())?;
+
+                if lines.is_empty() {
+                    return Err(TaskMgrError::TaskNotFound(command.to_string()));
+                }
+                Ok(lines.join("\n"))
+            }
+            // export dot|mermaid|gantt [where ...] [order by ...] [limit N] [offset M]
+            _ if command_equals(command, "export").unwrap() => {
+                let command = command_args(command);
+                let (format, query) = command.split_once(' ').unwrap_or((command, ""));
+                let Some(format) = ExportFormat::from_name(format) else {
+                    return Err(TaskMgrError::WrongQuery(command.to_string()));
+                };
+
+                let tasks = self.select(&format!("select * {}", query))?;
+                if tasks.is_empty() {
+                    return Err(TaskMgrError::TaskNotFound(command.to_string()));
                 }
-                Err(deleted.unwrap_err())
+                Ok(format.render(&tasks, self.dates.zone()))
+            }
+            _ if command_equals(command, "order").unwrap() => {
+                let dependencies = self.dependencies()?;
+                let order = dependencies
+                    .execution_order()
+                    .map_err(TaskMgrError::DependencyCycleError)?;
+                if order.is_empty() {
+                    return Err(TaskMgrError::TaskNotFound(command.trim().to_string()));
+                }
+
+                let lines: Vec<String> = order
+                    .iter()
+                    .enumerate()
+                    .map(|(i, task)| {
+                        let mut line = format!(
+                            "{}. #{} {}, due {}",
+                            i + 1,
+                            task.id,
+                            task.name,
+                            self.dates.format(task.date, task.zone)
+                        );
+                        let blockers: Vec<String> = dependencies
+                            .open_blockers(task)
+                            .iter()
+                            .map(|id| format!("#{}", id))
+                            .collect();
+                        if !blockers.is_empty() {
+                            line.push_str(&format!(", after {}", blockers.join(", ")));
+                        }
+                        line
+                    })
+                    .collect();
+                Ok(format!("Execution order:\n{}", lines.join("\n")))
             }
             _ if command_equals(command, "select").unwrap() => {
-                let selected = self.select(&command.to_string());
+                let selected = self.query(command);
                 if let Ok(result) = selected {
-                    if result.is_empty() {
-                        return Err(TaskMgrError::TaskNotFound);
+                    let lines: Vec<String> = match &result {
+                        QueryResult::Rows(rows) => rows
+                            .iter()
+                            .map(|row| row.render(self.dates.display_format()))
+                            .collect(),
+                        QueryResult::Counts(counts) => {
+                            counts.iter().map(|count| count.to_string()).collect()
+                        }
+                    };
+                    if lines.is_empty() {
+                        return Err(TaskMgrError::TaskNotFound(command.trim().to_string()));
                     }
-                    return Ok(format!("Selected: {:?}", result));
+                    return Ok(format!("Selected:\n{}", lines.join("\n")));
                 }
                 Err(selected.unwrap_err())
             }
             _ if command_equals(command, "done").unwrap() => {
-                let command = command.strip_prefix("done").unwrap().trim();
+                let command = command_args(command);
                 let marked_done = self.check_done(command);
-                if let Ok(result) = marked_done {
-                    return Ok(format!("Task marked as done: {:?}", result));
+                if let Ok((result, next)) = marked_done {
+                    let row = Row::project(&result, &LeftVar::ALL, true, self.dates.zone());
+                    let mut message = format!(
+                        "Task marked as done: {}",
+                        row.render(self.dates.display_format())
+                    );
+                    if let Some(next) = next {
+                        message.push_str(&format!(
+                            "\nNext occurrence: #{}, due {}",
+                            next.id,
+                            self.dates.format(next.date, next.zone)
+                        ));
+                    }
+                    return Ok(message);
                 }
                 Err(marked_done.unwrap_err())
             }
-            // update old_name, new_name, descrip, date, cat
+            // start, block, cancel or reopen task_ref
+            _ if status_command(command).is_some() => {
+                let (_, status) = status_command(command).unwrap();
+                let command = command_args(command);
+                let (result, _) = self.set_status(command, status)?;
+                let row = Row::project(&result, &LeftVar::ALL, true, self.dates.zone());
+                Ok(format!(
+                    "Task is {} now: {}",
+                    status,
+                    row.render(self.dates.display_format())
+                ))
+            }
+            // update old_name, new_name, descrip, date, cat[, key=value...]
             _ if command_equals(command, "update").unwrap() => {
                 // old_name new_name, descrip, date, cat
-                let command = command.strip_prefix("update").unwrap().trim();
-                // [old_name, new_name, descrip, date, cat]
+                let command = command_args(command);
+                // [old_name, new_name, descrip, date, cat, options...]
                 let composed: Vec<&str> = command.split(";").map(|field| field.trim()).collect();
-                if composed.len() != 5 {
-                    return Err(TaskMgrError::WrongQuery);
+                if composed.len() < 5 {
+                    return Err(TaskMgrError::WrongQuery(command.to_string()));
                 }
 
-                let updated = self.update(
-                    &composed[0],
-                    &composed[1],
-                    &composed[2],
-                    &composed[3],
-                    &composed[4],
+                let updated = self.update_with_options(
+                    composed[0],
+                    composed[1],
+                    composed[2],
+                    composed[3],
+                    composed[4],
+                    &composed[5..],
                 );
 
-                if let Ok(_) = updated {
-                    return Ok(format!("Task updated"));
+                if let Ok(task) = updated {
+                    return Ok(format!(
+                        "Task updated, due {}",
+                        self.dates.format(task.date, task.zone)
+                    ));
                 }
 
                 Err(updated.unwrap_err())
             }
-            _ => Err(TaskMgrError::WrongCommand),
+            _ => Err(TaskMgrError::WrongCommand(
+                command.split_whitespace().next().unwrap_or("").to_string(),
+            )),
+        }
+    }
+
+    /// Looks up a task by reference: `#3` addresses the task with id 3, anything else is a name.
+    /// Without a task with that id `#3` is taken as a name too.
+    ///
+    /// Returns [TaskMgrError::AmbiguousTaskName] when several tasks share the name.
+    pub fn find(&self, task_ref: &str) -> Result<Task, TaskMgrError> {
+        let mut task = self.lookup(task_ref)?;
+        self.blockers(std::slice::from_ref(&task))?
+            .mark_blocked(std::slice::from_mut(&mut task));
+        Ok(task)
+    }
+
+    /// [TaskManager::find] as the task is stored
+    fn lookup(&self, task_ref: &str) -> Result<Task, TaskMgrError> {
+        let task_ref = task_ref.trim();
+        if let Some(id) = task_ref
+            .strip_prefix('#')
+            .and_then(|id| id.parse::<u64>().ok())
+        {
+            if let Some(task) = self.storage.find(id)? {
+                return Ok(task);
+            }
+        }
+
+        let name_filter = Expr::Comparison(LeftVar::Name, Op::Equals, task_ref.to_string());
+        let mut found = self.storage.select(&name_filter)?;
+        match found.len() {
+            0 => Err(TaskMgrError::TaskNotFound(task_ref.to_string())),
+            1 => Ok(found.remove(0)),
+            _ => Err(TaskMgrError::AmbiguousTaskName(
+                task_ref.to_string(),
+                found.iter().map(|task| task.id).collect(),
+            )),
+        }
+    }
+
+    /// Stores a new task and returns the id it was given
+    pub fn add(&mut self, task: Task) -> Result<u64, TaskMgrError> {
+        self.check_links(&task)?;
+        self.insert_task(task)
+    }
+
+    /// Reverts the latest command and returns it, failing with [TaskMgrError::NothingToUndo]
+    pub fn undo(&mut self) -> Result<Revision, TaskMgrError> {
+        let revision = self
+            .history
+            .undoable()
+            .next()
+            .cloned()
+            .ok_or(TaskMgrError::NothingToUndo)?;
+        revision.revert(&mut self.storage)?;
+        self.history.undo();
+        Ok(revision)
+    }
+
+    /// Runs the latest undone command again and returns it,
+    /// failing with [TaskMgrError::NothingToRedo]
+    pub fn redo(&mut self) -> Result<Revision, TaskMgrError> {
+        let revision = self
+            .history
+            .redoable()
+            .next()
+            .cloned()
+            .ok_or(TaskMgrError::NothingToRedo)?;
+        revision.apply(&mut self.storage)?;
+        self.history.redo();
+        Ok(revision)
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Stores the task and records it for [TaskManager::undo], as do the two below
+    fn insert_task(&mut self, mut task: Task) -> Result<u64, TaskMgrError> {
+        task.id = self.storage.insert(task.clone())?;
+        let id = task.id;
+        self.record(Change::Insert(task));
+        Ok(id)
+    }
+
+    fn update_task(&mut self, task: Task) -> Result<(), TaskMgrError> {
+        let before = self
+            .storage
+            .find(task.id)?
+            .ok_or_else(|| TaskMgrError::TaskNotFound(format!("#{}", task.id)))?;
+        self.storage.update(task.clone())?;
+        self.record(Change::Update {
+            before: Box::new(before),
+            after: Box::new(task),
+        });
+        Ok(())
+    }
+
+    fn remove_task(&mut self, id: u64) -> Result<Task, TaskMgrError> {
+        let task = self.storage.remove(id)?;
+        self.record(Change::Remove(task.clone()));
+        Ok(task)
+    }
+
+    fn record(&mut self, change: Change) {
+        if let Some(changes) = &mut self.changes {
+            changes.push(change);
+        }
+    }
+
+    /// All of the tasks along with what they wait on
+    pub fn dependencies(&self) -> Result<Dependencies, TaskMgrError> {
+        Ok(Dependencies::new(self.storage.list()?))
+    }
+
+    /// Just the tasks the given ones wait on, enough for [Dependencies::open_blockers]
+    /// and [Dependencies::mark_blocked] of them
+    fn blockers(&self, tasks: &[Task]) -> Result<Dependencies, TaskMgrError> {
+        let ids: BTreeSet<u64> = tasks
+            .iter()
+            .flat_map(|task| task.blocked_by.iter().copied())
+            .collect();
+        let mut blockers = Vec::new();
+        for id in ids {
+            blockers.extend(self.storage.find(id)?);
+        }
+        Ok(Dependencies::new(blockers))
+    }
+
+    /// The open tasks in an order that has each of them after the ones it waits on,
+    /// see [Dependencies::execution_order]
+    pub fn execution_order(&self) -> Result<Vec<Task>, TaskMgrError> {
+        let dependencies = self.dependencies()?;
+        let order = dependencies
+            .execution_order()
+            .map_err(TaskMgrError::DependencyCycleError)?;
+        Ok(order.into_iter().cloned().collect())
+    }
+
+    /// All of the tasks arranged by their parents
+    pub fn tree(&self) -> Result<TaskTree, TaskMgrError> {
+        Ok(TaskTree::new(self.storage.list()?))
+    }
+
+    /// One line per task, each under its parent, only the `root` task and its subtasks with one
+    fn render_tree(&self, root: Option<&Task>) -> Result<Vec<String>, TaskMgrError> {
+        let tree = self.tree()?;
+        let (mut lines, walked, indent) = match root {
+            Some(root) => (
+                vec![self.render_tree_line(&tree, 0, root)],
+                tree.walk(Some(root.id)),
+                1,
+            ),
+            None => (Vec::new(), tree.walk(None), 0),
+        };
+
+        lines.extend(
+            walked
+                .into_iter()
+                .map(|(depth, task)| self.render_tree_line(&tree, depth + indent, task)),
+        );
+        Ok(lines)
+    }
+
+    /// `#2 name [status], due date, 1/3 subtasks done`, indented by how deep it is
+    fn render_tree_line(&self, tree: &TaskTree, depth: usize, task: &Task) -> String {
+        let mut line = format!(
+            "{}#{} {} [{}], due {}",
+            "  ".repeat(depth),
+            task.id,
+            task.name,
+            task.status,
+            self.dates.format(task.date, task.zone)
+        );
+        if let Some((done, total)) = tree.progress(task.id) {
+            line.push_str(&format!(", {}/{} subtasks done", done, total));
         }
+        line
+    }
+
+    /// Turns the task references of `parent=` and `blocked_by=` into `#id`,
+    /// [Task::set_option] only knows ids
+    fn resolve_task_refs(&self, option: &str) -> Result<String, TaskMgrError> {
+        let Some((key, value)) = option.split_once('=') else {
+            return Ok(option.to_string());
+        };
+        let task_refs: Vec<&str> = match key.trim().to_lowercase().as_str() {
+            "parent" => vec![value],
+            "blocked_by" | "blocked_by+" | "blocked_by-" => value.split(',').collect(),
+            _ => return Ok(option.to_string()),
+        };
+
+        let ids = task_refs
+            .into_iter()
+            .map(str::trim)
+            .filter(|task_ref| !task_ref.is_empty())
+            .map(|task_ref| match task_ref {
+                _ if task_ref.eq_ignore_ascii_case("none") => Ok(task_ref.to_string()),
+                _ => Ok(format!("#{}", self.lookup(task_ref)?.id)),
+            })
+            .collect::<Result<Vec<String>, TaskMgrError>>()?;
+        Ok(format!("{}={}", key, ids.join(", ")))
     }
 
-    pub fn find(&self, task_name: &str) -> Result<&Task, TaskMgrError> {
-        self.tasks
+    /// Makes sure the tasks the task refers to exist and don't lead back to it,
+    /// neither through their parents nor through what they wait on
+    fn check_links(&self, task: &Task) -> Result<(), TaskMgrError> {
+        self.check_parent(task)?;
+
+        let dependencies = self.dependencies()?;
+        if let Some(missing) = task
+            .blocked_by
             .iter()
-            .find(|task| task.name == task_name)
-            .ok_or(TaskMgrError::TaskNotFound)
+            .find(|id| **id != task.id && dependencies.get(**id).is_none())
+        {
+            return Err(TaskMgrError::TaskNotFound(format!("#{}", missing)));
+        }
+        if let Some(cycle) = dependencies.cycle(task.id, &task.blocked_by) {
+            return Err(TaskMgrError::DependencyCycleError(cycle));
+        }
+        Ok(())
     }
 
-    pub fn add(&mut self, task: Task) -> Result<(), TaskMgrError> {
-        self.tasks.push(task);
+    /// Makes sure the parent of the task exists and is neither the task nor one of its subtasks
+    fn check_parent(&self, task: &Task) -> Result<(), TaskMgrError> {
+        let Some(parent) = task.parent else {
+            return Ok(());
+        };
+        if parent == task.id {
+            return Err(TaskMgrError::SubtaskCycleError(task.id, parent));
+        }
+
+        let tree = self.tree()?;
+        if tree.get(parent).is_none() {
+            return Err(TaskMgrError::TaskNotFound(format!("#{}", parent)));
+        }
+        if tree
+            .ancestors(parent)
+            .iter()
+            .any(|ancestor| ancestor.id == task.id)
+        {
+            return Err(TaskMgrError::SubtaskCycleError(task.id, parent));
+        }
         Ok(())
     }
 
     pub fn update(
         &mut self,
-        task_name: &str,
+        task_ref: &str,
+        name: &str,
+        desc: &str,
+        date: &str,
+        cat: &str,
+    ) -> Result<Task, TaskMgrError> {
+        self.update_with_options(task_ref, name, desc, date, cat, &[])
+    }
+
+    /// Same as [TaskManager::update], also applying `key=value` options like `repeat=weekly`
+    pub fn update_with_options(
+        &mut self,
+        task_ref: &str,
         name: &str,
         desc: &str,
         date: &str,
         cat: &str,
-    ) -> Result<(), TaskMgrError> {
-        let task = self.tasks.iter_mut().find(|task| task.name == task_name);
-        if let Some(task) = task {
-            if let Err(e) = task.update(name, desc, date, cat) {
-                return Err(TaskMgrError::TaskUpdateErrorPropTask(e));
+        options: &[&str],
+    ) -> Result<Task, TaskMgrError> {
+        let mut task = match self.find(task_ref) {
+            Err(TaskMgrError::TaskNotFound(task_ref)) => {
+                return Err(TaskMgrError::TaskUpdateError(task_ref))
             }
+            found => found?,
+        };
 
-            return Ok(());
+        if let Err(e) = task.update_with(name, desc, date, cat, &self.dates) {
+            return Err(TaskMgrError::TaskUpdateErrorPropTask(e));
+        }
+        for option in options {
+            task.set_option(&self.resolve_task_refs(option)?, &self.dates)
+                .map_err(TaskMgrError::TaskUpdateErrorPropTask)?;
         }
+        self.check_links(&task)?;
 
-        Err(TaskMgrError::TaskUpdateError)
+        self.update_task(task.clone())?;
+        Ok(task)
     }
 
-    pub fn check_done(&mut self, task_name: &str) -> Result<&Task, TaskMgrError> {
-        let task = self.tasks.iter_mut().find(|task| task.name.eq(task_name));
-        if let Some(e) = task {
-            e.check_done();
-            return Ok(e);
-        } else {
-            return Err(TaskMgrError::TaskNotFound);
-        }
+    /// Marks the task as done, see [TaskManager::set_status]
+    pub fn check_done(&mut self, task_ref: &str) -> Result<(Task, Option<Task>), TaskMgrError> {
+        self.set_status(task_ref, Status::Done)
     }
 
-    pub fn delete(&mut self, task_name: &str) -> Result<(), TaskMgrError> {
-        if let Some(index) = self.tasks.iter().position(|task| task.name == task_name) {
-            self.tasks.remove(index);
-            return Ok(());
+    /// Moves the task to `status` if its lifecycle allows it.
+    ///
+    /// Completing a recurring task schedules its next occurrence as a new task,
+    /// returned along with the completed one.
+    ///
+    /// A task can't be started or completed while it waits on open tasks,
+    /// with [TaskManager::with_strict_subtasks] nor completed while it has open subtasks.
+    pub fn set_status(
+        &mut self,
+        task_ref: &str,
+        status: Status,
+    ) -> Result<(Task, Option<Task>), TaskMgrError> {
+        let mut task = self.find(task_ref)?;
+        task.transition(status)
+            .map_err(TaskMgrError::TaskUpdateErrorPropTask)?;
+        if matches!(status, Status::InProgress | Status::Done) {
+            let blockers = self
+                .blockers(std::slice::from_ref(&task))?
+                .open_blockers(&task);
+            if !blockers.is_empty() {
+                return Err(TaskMgrError::WaitingOnError(
+                    task_ref.trim().to_string(),
+                    blockers,
+                ));
+            }
+        }
+        if status == Status::Done && self.strict_subtasks {
+            let open = self
+                .tree()?
+                .children(Some(task.id))
+                .iter()
+                .filter(|subtask| !matches!(subtask.status, Status::Done | Status::Cancelled))
+                .count();
+            if open > 0 {
+                return Err(TaskMgrError::OpenSubtasksError(
+                    task_ref.trim().to_string(),
+                    open,
+                ));
+            }
         }
 
-        Err(TaskMgrError::TaskNotFound)
-    }
+        let mut next = None;
+        if task.status == Status::Done {
+            next = task.next_occurrence(self.dates.zone());
+        }
+        if next.is_some() {
+            // the rule moves on to the next occurrence
+            task.recurrence = None;
+        }
+        self.update_task(task.clone())?;
 
-    pub fn select(&self, query: &String) -> Result<Vec<Task>, TaskMgrError> {
-        let query = query.trim();
-        if query.is_empty() {
-            return Err(TaskMgrError::WrongQuery);
+        if let Some(next) = &mut next {
+            next.id = self.insert_task(next.clone())?;
         }
+        Ok((task, next))
+    }
+
+    /// Deletes the task, its subtasks move up to its parent
+    pub fn delete(&mut self, task_ref: &str) -> Result<(), TaskMgrError> {
+        self.delete_with(task_ref, OnDelete::Reparent).map(|_| ())
+    }
 
-        if query == "select *" {
-            return Ok(self.tasks.clone());
+    /// Deletes the task and then moves or deletes its subtasks as `on_delete` says,
+    /// the tasks waiting on the deleted ones stop waiting.
+    ///
+    /// Returns the subtasks as they were before, the direct ones for [OnDelete::Reparent]
+    /// and all of them for [OnDelete::Cascade].
+    pub fn delete_with(
+        &mut self,
+        task_ref: &str,
+        on_delete: OnDelete,
+    ) -> Result<Vec<Task>, TaskMgrError> {
+        let task = self.find(task_ref)?;
+        let tree = self.tree()?;
+        let subtasks: Vec<Task> = match on_delete {
+            OnDelete::Reparent => tree.children(Some(task.id)),
+            OnDelete::Cascade => tree.descendants(task.id),
         }
+        .into_iter()
+        .cloned()
+        .collect();
 
-        let query = query.replace("select * where", "");
+        self.remove_task(task.id)?;
+        for subtask in &subtasks {
+            match on_delete {
+                OnDelete::Reparent => self.update_task(Task {
+                    parent: task.parent,
+                    ..subtask.clone()
+                })?,
+                OnDelete::Cascade => {
+                    self.remove_task(subtask.id)?;
+                }
+            }
+        }
 
-        let parsed_args = parse_args(&query);
-        if let Err(e) = parsed_args {
-            return Err(TaskMgrError::WrongQueryPropExpr(e));
+        let mut deleted = BTreeSet::from([task.id]);
+        if on_delete == OnDelete::Cascade {
+            deleted.extend(subtasks.iter().map(|subtask| subtask.id));
+        }
+        for mut waiting in self.storage.list()? {
+            if !waiting.blocked_by.is_disjoint(&deleted) {
+                waiting.blocked_by.retain(|id| !deleted.contains(id));
+                self.update_task(waiting)?;
+            }
         }
+        Ok(subtasks)
+    }
 
-        let parsed_args = parsed_args.unwrap();
+    /// Runs any `select` query: rows for `select *` and column lists,
+    /// counts for `select count(*) [where ...] [group by ...]`
+    pub fn query(&self, query: &str) -> Result<QueryResult, TaskMgrError> {
+        let query = self.parse_query(query)?;
 
-        let mut temp = self.tasks.clone();
+        if query.projection == Projection::Count {
+            return Ok(QueryResult::Counts(self.count(&query)?));
+        }
 
-        for arg in parsed_args {
-            temp = temp
-                .iter()
-                .filter(|task| {
-                    self.match_field(&arg, task).is_ok() && self.match_field(&arg, task).unwrap()
-                })
-                .cloned()
-                .collect();
+        Ok(QueryResult::Rows(self.project(&query)?))
+    }
+
+    /// Runs a `select (* | columns) [where ...] [order by ...] [limit N] [offset M]` query
+    /// and cuts the selected tasks down to the requested columns
+    pub fn select_rows(&self, query_text: &str) -> Result<Vec<Row>, TaskMgrError> {
+        let query = self.parse_query(query_text)?;
+        if query.projection == Projection::Count {
+            return Err(TaskMgrError::WrongQuery(query_text.to_string()));
         }
 
-        Ok(temp)
+        self.project(&query)
     }
 
-    fn match_field(
-        &self,
-        (leftvar, op, other): &(LeftVar, Op, String),
-        task: &Task,
-    ) -> Result<bool, TaskMgrError> {
-        match leftvar {
-            LeftVar::Name => Ok(self.compare_with_op(&task.name.to_string(), other, op)),
-            LeftVar::Description => {
-                Ok(self.compare_with_op(&task.description.to_string(), other, op))
-            }
-            LeftVar::Date => {
-                let parsed_date = parse_task_date(other.clone());
-                if let Err(e) = parsed_date {
-                    return Err(TaskMgrError::GeneralTaskError(e));
-                }
-                return Ok(self.compare_with_op(&task.date, &parsed_date.unwrap(), op));
-            }
-            LeftVar::Category => Ok(self.compare_with_op(&task.category.to_string(), other, op)),
-            LeftVar::Done => Ok(self.compare_with_op(&task.done.to_string(), other, op)),
+    fn project(&self, query: &Query) -> Result<Vec<Row>, TaskMgrError> {
+        let keep_id = query.projection == Projection::All;
+
+        Ok(self
+            .run_query(query)?
+            .iter()
+            .map(|task| Row::project(task, query.projection.columns(), keep_id, self.dates.zone()))
+            .collect())
+    }
+
+    /// Counts the filtered tasks per group, `limit` and `offset` page through the groups
+    fn count(&self, query: &Query) -> Result<Vec<AggregateRow>, TaskMgrError> {
+        let tasks = self.filter(query)?;
+
+        Ok(
+            AggregateRow::count(&tasks, query.group_by, self.dates.zone())
+                .into_iter()
+                .skip(query.offset)
+                .take(query.limit.unwrap_or(usize::MAX))
+                .collect(),
+        )
+    }
+
+    /// Runs a `select` query and returns whole tasks, whatever columns it asks for
+    ///
+    /// Without `order by` the most urgent tasks come first, in insertion order within a priority.
+    pub fn select(&self, query: &str) -> Result<Vec<Task>, TaskMgrError> {
+        let query = self.parse_query(query)?;
+        self.run_query(&query)
+    }
+
+    fn parse_query(&self, query: &str) -> Result<Query, TaskMgrError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(TaskMgrError::WrongQuery(query.to_string()));
         }
+
+        parse_query_with(query, Some(&self.dates)).map_err(TaskMgrError::WrongQueryPropExpr)
     }
 
-    /// Because of the disruptive [NaiveDateTime] and [bool] in our [Task] struct fields
-    /// we need a generic function to compare all of the involved types.
-    /// Thankfully [NaiveDateTime] already implements [PartialEq] and [PartialOrd]
-    /// so we can easily use comparison operators between them, but we also need `<T>`
-    /// to implement [Display], since we're not converting [String] input to [bool], but instead comparing their `to_string()` values.
+    /// Tasks passing the `where` part of the query, all of them without one
     ///
-    /// ```assert_eq!(true.to_string(), "true");```
-    fn compare_with_op<T>(&self, one: &T, other: &T, op: &Op) -> bool
-    where
-        T: PartialEq + PartialOrd + Display,
-    {
-        match op {
-            Op::Equals => one.eq(other),
-            Op::NotEquals => one.ne(other),
-            Op::Greater => one.gt(other),
-            Op::GrEquals => one.ge(other),
-            Op::Less => one.lt(other),
-            Op::LeEquals => one.le(other),
-            Op::Like => one.to_string().contains(&other.to_string()),
+    /// The storage evaluates the filters on [Task::is_blocked] too, it is filled in here for
+    /// the selected tasks only, loading just the tasks they wait on.
+    fn filter(&self, query: &Query) -> Result<Vec<Task>, TaskMgrError> {
+        let mut selected = match &query.filter {
+            Some(filter) => self.storage.select(filter)?,
+            None => self.storage.list()?,
+        };
+
+        self.blockers(&selected)?.mark_blocked(&mut selected);
+        Ok(selected)
+    }
+
+    fn run_query(&self, query: &Query) -> Result<Vec<Task>, TaskMgrError> {
+        let mut selected = self.filter(query)?;
+
+        if query.order_by.is_empty() {
+            // stable, so equally urgent tasks stay in insertion order
+            selected.sort_by(|one, other| other.cmp_by(one, LeftVar::Priority));
+        } else {
+            selected.sort_by(|one, other| {
+                query
+                    .order_by
+                    .iter()
+                    .map(|(field, order)| match order {
+                        SortOrder::Asc => one.cmp_by(other, *field),
+                        SortOrder::Desc => other.cmp_by(one, *field),
+                    })
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
         }
+
+        Ok(selected
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect())
     }
 }
+
+/// Commands moving a task to a status, `done` has its own since it schedules repeats
+const STATUS_COMMANDS: [(&str, Status); 4] = [
+    ("start", Status::InProgress),
+    ("block", Status::Blocked),
+    ("cancel", Status::Cancelled),
+    ("reopen", Status::Todo),
+];
+
+fn status_command(command: &str) -> Option<(&'static str, Status)> {
+    STATUS_COMMANDS
+        .into_iter()
+        .find(|(name, _)| command_equals(command, name).unwrap_or(false))
+}
+
+/// What follows the command name, trimmed, `#3` for ` start  #3 `
+fn command_args(command: &str) -> &str {
+    command
+        .trim()
+        .split_once(char::is_whitespace)
+        .map_or("", |(_, args)| args.trim())
+}
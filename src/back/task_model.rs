@@ -1,40 +1,298 @@
 #![allow(dead_code)]
+use super::dates::{localize, DateParser};
+use super::expression::{split_tags, Expr, LeftVar, Op};
+use super::recurrence::Recurrence;
+use super::rows::Value;
 use super::TaskError;
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Datelike, NaiveDateTime, SubsecRound, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
 
-#[derive(Debug, Clone)]
+/// Where a task is in its lifecycle, in lifecycle order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    #[default]
+    Todo,
+    InProgress,
+    Blocked,
+    Done,
+    Cancelled,
+}
+
+impl Status {
+    pub const ALL: [Status; 5] = [
+        Status::Todo,
+        Status::InProgress,
+        Status::Blocked,
+        Status::Done,
+        Status::Cancelled,
+    ];
+
+    /// Name of the status as written in commands and queries
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Todo => "todo",
+            Status::InProgress => "in-progress",
+            Status::Blocked => "blocked",
+            Status::Done => "done",
+            Status::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Status> {
+        let name = name.trim().to_lowercase();
+        Status::ALL.into_iter().find(|status| status.name() == name)
+    }
+
+    /// Like [Status::from_name], also taking the spellings people tend to type:
+    /// `in progress`, `in_progress`, `started`, `completed`, `canceled`...
+    pub fn parse(name: &str) -> Option<Status> {
+        let name = name.trim().to_lowercase().replace(['_', ' '], "-");
+        match name.as_str() {
+            "to-do" | "open" | "new" => Some(Status::Todo),
+            "inprogress" | "started" | "doing" | "active" => Some(Status::InProgress),
+            "completed" | "complete" | "finished" | "closed" => Some(Status::Done),
+            "canceled" => Some(Status::Cancelled),
+            _ => Status::from_name(&name),
+        }
+    }
+
+    /// Whether a task may go from this status to `to`:
+    ///
+    /// - work starts, gets blocked, finished or cancelled from `todo` and `in-progress`
+    /// - a blocked task is unblocked into `todo` or `in-progress`, or cancelled
+    /// - finished and cancelled tasks can only be reopened into `todo`
+    pub fn can_become(self, to: Status) -> bool {
+        use Status::*;
+        matches!(
+            (self, to),
+            (Todo, InProgress | Blocked | Done | Cancelled)
+                | (InProgress, Todo | Blocked | Done | Cancelled)
+                | (Blocked, Todo | InProgress | Cancelled)
+                | (Done | Cancelled, Todo)
+        )
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// How important a task is, from the least to the most urgent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 4] = [
+        Priority::Low,
+        Priority::Normal,
+        Priority::High,
+        Priority::Urgent,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+
+    /// Takes a name or its rank, `1` for low up to `4` for urgent
+    pub fn parse(name: &str) -> Option<Priority> {
+        let name = name.trim().to_lowercase();
+        if let Ok(rank) = name.parse::<usize>() {
+            return rank
+                .checked_sub(1)
+                .and_then(|i| Priority::ALL.get(i).copied());
+        }
+        Priority::ALL
+            .into_iter()
+            .find(|priority| priority.name() == name)
+    }
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
+    /// Assigned by the [Storage](crate::back::storage::Storage) on insertion, `0` until then
+    #[serde(default)]
+    pub id: u64,
     pub name: String,
     pub description: String,
-    pub date: NaiveDateTime,
+    /// Due date, documents written before dates had zones are read as UTC
+    #[serde(deserialize_with = "deserialize_date")]
+    pub date: DateTime<Utc>,
+    /// Zone the due date was entered in, [None] follows the configured default zone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zone: Option<Tz>,
     pub category: String,
-    pub done: bool,
+    /// Documents written before the lifecycle have a `done` flag instead
+    #[serde(alias = "done", deserialize_with = "deserialize_status")]
+    pub status: Status,
+    /// Set with the `priority=` option
+    #[serde(default)]
+    pub priority: Priority,
+    /// Set with the `tags=` option, a task can be in several contexts at once
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// Id of the task this one is a subtask of, set with the `parent=` option
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<u64>,
+    /// Ids of the tasks that have to be done before this one starts, set with the `blocked_by=` option
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub blocked_by: BTreeSet<u64>,
+    /// Whether some of [Task::blocked_by] is neither done nor cancelled.
+    ///
+    /// Depends on the other tasks, so it is not stored but filled in by the
+    /// [TaskManager](crate::back::task_manager::TaskManager) whenever it reads tasks.
+    #[serde(skip)]
+    pub is_blocked: bool,
+    /// Set with the `repeat=` option, see [Recurrence]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
 }
 
 impl Task {
     pub fn new(task: &str) -> Result<Self, TaskError> {
+        Self::new_with(task, &DateParser::default())
+    }
+
+    /// Same as [Task::new], resolving relative dates like `tomorrow` with `dates`.
+    ///
+    /// The four fields can be followed by `key=value` options, see [Task::set_option].
+    pub fn new_with(task: &str, dates: &DateParser) -> Result<Self, TaskError> {
         let task: Vec<String> = task
             .split(";")
             .map(|task| task.trim().to_string())
             .collect();
 
-        if task.len() != 4 {
-            return Err(TaskError::TaskCreationArgsError);
+        if task.len() < 4 {
+            return Err(TaskError::TaskCreationArgsError(task.len()));
         }
 
-        let parsed = parse_task_date(task[2].to_string())?;
+        let (date, zone) = dates.resolve(&task[2], None)?;
 
-        Ok(Task {
+        let mut created = Task {
+            id: 0,
             name: task[0].to_string(),
             description: task[1].to_string(),
-            date: parsed,
+            date,
+            zone,
             category: task[3].to_string(),
-            done: false,
+            status: Status::Todo,
+            priority: Priority::Normal,
+            tags: BTreeSet::new(),
+            parent: None,
+            blocked_by: BTreeSet::new(),
+            is_blocked: false,
+            recurrence: None,
+        };
+        for option in &task[4..] {
+            created.set_option(option, dates)?;
+        }
+
+        Ok(created)
+    }
+
+    /// Applies a `key=value` option given after the fields of `add` and `update`:
+    ///
+    /// - `repeat=<rule>` makes the task recurring, `repeat=none` stops it, see [Recurrence]
+    /// - `priority=<low|normal|high|urgent>` or `priority=1..4` sets the [Priority]
+    /// - `tags=a, b` replaces the tags, `tags+=c` adds to them and `tags-=a` removes from them
+    /// - `parent=#3` makes the task a subtask of task 3, `parent=none` a top level one again
+    /// - `blocked_by=#1, #2` makes the task wait on tasks 1 and 2, `+=` and `-=` work as for tags
+    pub fn set_option(&mut self, option: &str, dates: &DateParser) -> Result<(), TaskError> {
+        let error = || TaskError::TaskOptionError(option.trim().to_string());
+        let (key, value) = option.split_once('=').ok_or_else(error)?;
+        let value = value.trim();
+
+        match key.trim().to_lowercase().as_str() {
+            "repeat" if value.eq_ignore_ascii_case("none") => self.recurrence = None,
+            "repeat" => self.recurrence = Some(Recurrence::parse(value, dates, self.zone)?),
+            "priority" => self.priority = Priority::parse(value).ok_or_else(error)?,
+            "tags" => self.tags = split_tags(value).collect(),
+            "tags+" => self.tags.extend(split_tags(value)),
+            "parent" if value.eq_ignore_ascii_case("none") => self.parent = None,
+            "parent" => {
+                let id = value.strip_prefix('#').unwrap_or(value);
+                self.parent = Some(id.parse().map_err(|_| error())?);
+            }
+            "tags-" => {
+                for tag in split_tags(value) {
+                    self.tags.remove(&tag);
+                }
+            }
+            "blocked_by" => self.blocked_by = parse_ids(value).ok_or_else(error)?,
+            "blocked_by+" => self.blocked_by.extend(parse_ids(value).ok_or_else(error)?),
+            "blocked_by-" => {
+                for id in parse_ids(value).ok_or_else(error)? {
+                    self.blocked_by.remove(&id);
+                }
+            }
+            _ => return Err(error()),
+        }
+        Ok(())
+    }
+
+    /// The task as it is due next time when it repeats, in `todo` and without an id.
+    ///
+    /// Occurrences keep their wall clock time in the task's zone, or `default_zone` without one.
+    /// [None] once the rule's `until` or `count` is used up.
+    pub fn next_occurrence(&self, default_zone: Tz) -> Option<Task> {
+        let rule = self.recurrence.as_ref()?;
+        let count = match rule.count {
+            Some(1) => return None,
+            count => count.map(|count| count - 1),
+        };
+
+        let zone = self.zone.unwrap_or(default_zone);
+        let next = rule.next(self.date.with_timezone(&zone).naive_local())?;
+        let next = localize(next, zone).ok()?;
+        if rule.until.is_some_and(|until| next > until) {
+            return None;
+        }
+
+        Some(Task {
+            id: 0,
+            date: next,
+            status: Status::Todo,
+            recurrence: Some(Recurrence {
+                count,
+                day: rule.day.or(Some(self.date.with_timezone(&zone).day())),
+                ..rule.clone()
+            }),
+            ..self.clone()
         })
     }
 
-    pub fn check_done(&mut self) {
-        self.done = !self.done;
+    /// Moves the task to `to`, failing with [TaskError::IllegalTransitionError]
+    /// when [Status::can_become] does not allow it
+    pub fn transition(&mut self, to: Status) -> Result<(), TaskError> {
+        if !self.status.can_become(to) {
+            return Err(TaskError::IllegalTransitionError(self.status, to));
+        }
+        self.status = to;
+        Ok(())
     }
 
     pub fn update(
@@ -44,21 +302,194 @@ impl Task {
         date: &str,
         cat: &str,
     ) -> Result<&Self, TaskError> {
-        let parsed = parse_task_date(date.to_string())?;
+        self.update_with(name, description, date, cat, &DateParser::default())
+    }
+
+    /// Same as [Task::update], resolving relative dates like `tomorrow` with `dates`.
+    ///
+    /// A date without a zone is taken in the zone the task already has.
+    pub fn update_with(
+        &mut self,
+        name: &str,
+        description: &str,
+        date: &str,
+        cat: &str,
+        dates: &DateParser,
+    ) -> Result<&Self, TaskError> {
+        let (date, zone) = dates.resolve(date, self.zone)?;
         self.name = name.to_string();
         self.description = description.to_string();
-        self.date = parsed;
+        self.date = date;
+        self.zone = zone;
         self.category = cat.to_string();
 
         Ok(self)
     }
+
+    /// Due date in the task's own zone, or in `default_zone` without one
+    pub fn local_date(&self, default_zone: Tz) -> DateTime<Tz> {
+        self.date.with_timezone(&self.zone.unwrap_or(default_zone))
+    }
+
+    /// Value of one of the task's fields, the date in the task's zone or `default_zone`
+    pub fn get(&self, field: LeftVar, default_zone: Tz) -> Value {
+        match field {
+            LeftVar::Name => Value::Text(self.name.clone()),
+            LeftVar::Description => Value::Text(self.description.clone()),
+            LeftVar::Date => Value::Date(self.local_date(default_zone)),
+            LeftVar::Category => Value::Text(self.category.clone()),
+            LeftVar::Status => Value::Status(self.status),
+            LeftVar::Priority => Value::Priority(self.priority),
+            LeftVar::Tags => Value::Tags(self.tags.iter().cloned().collect()),
+            LeftVar::IsBlocked => Value::Bool(self.is_blocked),
+        }
+    }
+
+    /// Compares two tasks by one of their fields, using the field's natural order:
+    /// text alphabetically, dates chronologically, statuses in lifecycle order,
+    /// priorities from low to urgent, tags as sorted lists, unblocked tasks before blocked ones
+    pub fn cmp_by(&self, other: &Task, field: LeftVar) -> Ordering {
+        match field {
+            LeftVar::Name => self.name.cmp(&other.name),
+            LeftVar::Description => self.description.cmp(&other.description),
+            LeftVar::Date => self.date.cmp(&other.date),
+            LeftVar::Category => self.category.cmp(&other.category),
+            LeftVar::Status => self.status.cmp(&other.status),
+            LeftVar::Priority => self.priority.cmp(&other.priority),
+            LeftVar::Tags => self.tags.cmp(&other.tags),
+            LeftVar::IsBlocked => self.is_blocked.cmp(&other.is_blocked),
+        }
+    }
+
+    /// Evaluates the parsed `select` filter against the task
+    pub fn matches(&self, expr: &Expr) -> Result<bool, TaskError> {
+        match expr {
+            Expr::And(left, right) => Ok(self.matches(left)? && self.matches(right)?),
+            Expr::Or(left, right) => Ok(self.matches(left)? || self.matches(right)?),
+            Expr::Not(inner) => Ok(!self.matches(inner)?),
+            Expr::Comparison(leftvar, op, other) => self.compare_field(leftvar, op, other),
+        }
+    }
+
+    /// Checks the task against a single `field op value` comparison
+    fn compare_field(&self, leftvar: &LeftVar, op: &Op, other: &String) -> Result<bool, TaskError> {
+        match leftvar {
+            LeftVar::Name => Ok(compare_with_op(&self.name, other, op)),
+            LeftVar::Description => Ok(compare_with_op(&self.description, other, op)),
+            // filters hold UTC dates down to the second, see DateParser::canonicalize
+            LeftVar::Date => {
+                let parsed_date = parse_task_date(other.clone())?;
+                let date = self.date.naive_utc().trunc_subsecs(0);
+                Ok(compare_with_op(&date, &parsed_date, op))
+            }
+            LeftVar::Category => Ok(compare_with_op(&self.category, other, op)),
+            LeftVar::Status => Ok(self.compare_status(op, other)),
+            LeftVar::Priority => Ok(match (op, Priority::parse(other)) {
+                (Op::Like, _) | (_, None) => {
+                    compare_with_op(&self.priority.name().to_string(), other, op)
+                }
+                (_, Some(priority)) => compare_with_op(&self.priority, &priority, op),
+            }),
+            LeftVar::Tags => Ok(self.compare_tags(op, other)),
+            LeftVar::IsBlocked => Ok(compare_with_op(&self.is_blocked.to_string(), other, op)),
+        }
+    }
+
+    /// `has` looks for a single tag, `any` and `all` for a comma separated list of them
+    fn compare_tags(&self, op: &Op, other: &str) -> bool {
+        match op {
+            Op::Has => self.tags.contains(other),
+            Op::Any => split_tags(other).any(|tag| self.tags.contains(&tag)),
+            Op::All => split_tags(other).all(|tag| self.tags.contains(&tag)),
+            // the parser only lets tags be compared with the above
+            _ => false,
+        }
+    }
+
+    /// `true` and `false` ask whether the task is done, status names compare in lifecycle order
+    fn compare_status(&self, op: &Op, other: &str) -> bool {
+        if matches!(other, "true" | "false") {
+            let done = self.status == Status::Done;
+            return compare_with_op(&done.to_string(), &other.to_string(), op);
+        }
+
+        match (op, Status::from_name(other)) {
+            (Op::Like, _) | (_, None) => {
+                compare_with_op(&self.status.name().to_string(), &other.to_string(), op)
+            }
+            (_, Some(status)) => compare_with_op(&self.status, &status, op),
+        }
+    }
 }
 
+/// Compares two values of a field with `op`, the values being of whatever type the field is
+/// compared as: [NaiveDateTime] in UTC for dates, [Priority] and [Status] in their rank order,
+/// [String] for text and the `true`/`false` of booleans.
+///
+/// [Op::Like] looks for the `to_string()` of `other` in that of `one`, hence the [Display].
+fn compare_with_op<T>(one: &T, other: &T, op: &Op) -> bool
+where
+    T: PartialEq + PartialOrd + Display,
+{
+    match op {
+        Op::Equals => one.eq(other),
+        Op::NotEquals => one.ne(other),
+        Op::Greater => one.gt(other),
+        Op::GrEquals => one.ge(other),
+        Op::Less => one.lt(other),
+        Op::LeEquals => one.le(other),
+        Op::Like => one.to_string().contains(&other.to_string()),
+        // only tags take them, see Task::compare_tags
+        Op::Has | Op::Any | Op::All => false,
+    }
+}
+
+/// `#1, #2` or `1, 2`, [None] if one of them is not an id
+fn parse_ids(ids: &str) -> Option<BTreeSet<u64>> {
+    ids.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && !id.eq_ignore_ascii_case("none"))
+        .map(|id| id.strip_prefix('#').unwrap_or(id).parse().ok())
+        .collect()
+}
+
+/// Reads `%Y-%m-%d %H:%M`, or the [CANONICAL_FORMAT](crate::back::dates::CANONICAL_FORMAT)
+/// with seconds that `select` filters hold
 pub fn parse_task_date(date: String) -> Result<NaiveDateTime, TaskError> {
-    let parsed = NaiveDateTime::parse_from_str(&date, "%Y-%m-%d %H:%M");
-    if parsed.is_err() {
-        return Err(TaskError::TaskDateParseError);
+    NaiveDateTime::parse_from_str(&date, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(&date, "%Y-%m-%d %H:%M"))
+        .map_err(|_| TaskError::TaskDateParseError(date))
+}
+
+/// Reads RFC 3339 timestamps as well as the zoneless dates older documents hold
+fn deserialize_date<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let date = String::deserialize(deserializer)?;
+    DateTime::parse_from_rfc3339(&date)
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(&date, "%Y-%m-%dT%H:%M:%S%.f").map(|date| date.and_utc())
+        })
+        .map_err(serde::de::Error::custom)
+}
+
+/// Reads the status, or the `true`/`false` of the `done` flag it replaced
+fn deserialize_status<'de, D>(deserializer: D) -> Result<Status, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredStatus {
+        Done(bool),
+        Status(Status),
     }
 
-    return Ok(parsed.unwrap());
+    Ok(match StoredStatus::deserialize(deserializer)? {
+        StoredStatus::Done(true) => Status::Done,
+        StoredStatus::Done(false) => Status::Todo,
+        StoredStatus::Status(status) => status,
+    })
 }
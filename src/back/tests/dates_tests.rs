@@ -0,0 +1,263 @@
+use crate::back::dates::{DateParser, FixedClock};
+use crate::back::errors::TaskError;
+use crate::back::natural::Locale;
+use chrono::{NaiveDateTime, NaiveTime};
+use chrono_tz::{America, Europe};
+
+/// Wednesday afternoon
+fn parser() -> DateParser {
+    DateParser::new(FixedClock(date("2024-05-15 13:45").and_utc()))
+}
+
+fn date(date: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap()
+}
+
+#[test]
+fn absolute_date() {
+    assert_eq!(
+        parser().parse("2015-09-05 00:00").unwrap(),
+        date("2015-09-05 00:00")
+    );
+}
+
+#[test]
+fn anchors() {
+    let dates = parser();
+    assert_eq!(dates.parse("now").unwrap(), date("2024-05-15 13:45"));
+    assert_eq!(dates.parse("today").unwrap(), date("2024-05-15 00:00"));
+    assert_eq!(dates.parse("Tomorrow").unwrap(), date("2024-05-16 00:00"));
+    assert_eq!(dates.parse("yesterday").unwrap(), date("2024-05-14 00:00"));
+    assert_eq!(
+        dates.parse("start_of_week").unwrap(),
+        date("2024-05-13 00:00")
+    );
+    assert_eq!(
+        dates.parse("end_of_week").unwrap(),
+        date("2024-05-19 00:00")
+    );
+    assert_eq!(
+        dates.parse("start_of_month").unwrap(),
+        date("2024-05-01 00:00")
+    );
+    assert_eq!(
+        dates.parse("end_of_month").unwrap(),
+        date("2024-05-31 00:00")
+    );
+}
+
+#[test]
+fn offsets() {
+    let dates = parser();
+    assert_eq!(dates.parse("+3d").unwrap(), date("2024-05-18 13:45"));
+    assert_eq!(dates.parse("-2w").unwrap(), date("2024-05-01 13:45"));
+    assert_eq!(dates.parse("+30min").unwrap(), date("2024-05-15 14:15"));
+    assert_eq!(dates.parse("-1h").unwrap(), date("2024-05-15 12:45"));
+    assert_eq!(dates.parse("+1mo").unwrap(), date("2024-06-15 13:45"));
+    assert_eq!(dates.parse("-1y").unwrap(), date("2023-05-15 13:45"));
+}
+
+#[test]
+fn anchor_offsets_and_time() {
+    let dates = parser();
+    assert_eq!(
+        dates.parse("tomorrow 09:30").unwrap(),
+        date("2024-05-16 09:30")
+    );
+    assert_eq!(
+        dates.parse("start_of_week +1w").unwrap(),
+        date("2024-05-20 00:00")
+    );
+    assert_eq!(
+        dates.parse("today +1d -2h").unwrap(),
+        date("2024-05-15 22:00")
+    );
+}
+
+#[test]
+fn invalid_dates() {
+    let dates = parser();
+    for invalid in [
+        "",
+        "soon",
+        "3d",
+        "+3",
+        "+d",
+        "+3days",
+        "today tomorrow",
+        "2022-13-02",
+        "2022-09-02 25:00",
+    ] {
+        assert!(
+            matches!(dates.parse(invalid), Err(TaskError::TaskDateParseError(date)) if date == invalid),
+            "{} should not parse",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn natural_english() {
+    let dates = parser();
+    for (phrase, expected) in [
+        ("next friday 17:00", "2024-05-17 17:00"),
+        ("Friday", "2024-05-17 00:00"),
+        ("this wednesday", "2024-05-15 00:00"),
+        ("next wed", "2024-05-22 00:00"),
+        ("in 2 hours", "2024-05-15 15:45"),
+        ("in an hour", "2024-05-15 14:45"),
+        ("3 days ago", "2024-05-12 13:45"),
+        ("tomorrow at 5pm", "2024-05-16 17:00"),
+        ("monday 9:30 am", "2024-05-20 09:30"),
+        ("next week", "2024-05-20 00:00"),
+        ("next month", "2024-06-01 00:00"),
+        ("at noon", "2024-05-15 12:00"),
+        ("in 2 days at 10:00", "2024-05-17 10:00"),
+    ] {
+        assert_eq!(dates.parse(phrase).unwrap(), date(expected), "{}", phrase);
+    }
+}
+
+#[test]
+fn natural_russian() {
+    let dates = parser().with_locale(Locale::Russian);
+    for (phrase, expected) in [
+        ("в следующую пятницу в 17:00", "2024-05-17 17:00"),
+        ("в среду", "2024-05-15 00:00"),
+        ("через 2 часа", "2024-05-15 15:45"),
+        ("через час", "2024-05-15 14:45"),
+        ("послезавтра в 10:00", "2024-05-17 10:00"),
+        ("3 дня назад", "2024-05-12 13:45"),
+        ("на следующей неделе", "2024-05-20 00:00"),
+        ("Завтра в полдень", "2024-05-16 12:00"),
+    ] {
+        assert_eq!(dates.parse(phrase).unwrap(), date(expected), "{}", phrase);
+    }
+
+    // the relative literals work whatever the locale
+    assert_eq!(dates.parse("+3d").unwrap(), date("2024-05-18 13:45"));
+    assert!(dates.parse("in 2 hours").is_err());
+}
+
+#[test]
+fn natural_invalid() {
+    let dates = parser();
+    for invalid in [
+        "next",
+        "tomorrow today",
+        "friday friday",
+        "13pm",
+        "in 2",
+        "через 2 часа",
+    ] {
+        assert!(
+            dates.parse(invalid).is_err(),
+            "{} should not parse",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn default_input_formats() {
+    let dates = parser();
+    for (input, expected) in [
+        ("2024-05-01", "2024-05-01 00:00"),
+        ("01.05.2024 09:00", "2024-05-01 09:00"),
+        ("01.05.2024", "2024-05-01 00:00"),
+        ("2024-05-01 09:00:30", "2024-05-01 09:00"),
+        ("2024-05-01T09:00", "2024-05-01 09:00"),
+        ("2024-05-01T09:00:00", "2024-05-01 09:00"),
+        ("2024-05-01T09:00:00.250", "2024-05-01 09:00"),
+    ] {
+        let parsed = dates.parse(input).unwrap();
+        assert_eq!(
+            parsed.format("%Y-%m-%d %H:%M").to_string(),
+            expected,
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn default_time() {
+    let dates = parser().with_default_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap());
+    assert_eq!(dates.parse("2024-05-01").unwrap(), date("2024-05-01 09:00"));
+    assert_eq!(dates.parse("friday").unwrap(), date("2024-05-17 09:00"));
+    assert_eq!(
+        dates.parse("2024-05-01 18:00").unwrap(),
+        date("2024-05-01 18:00")
+    );
+}
+
+#[test]
+fn display_format() {
+    let dates = parser().with_display_format("%d.%m.%Y").unwrap();
+    assert_eq!(
+        dates.format(date("2024-05-01 09:00").and_utc(), None),
+        "01.05.2024"
+    );
+    assert!(parser().with_display_format("").is_err());
+    assert!(parser().with_input_formats(["%Y-%m-%d", "%Q"]).is_err());
+}
+
+fn utc(date: &str) -> String {
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M")
+        .unwrap()
+        .and_utc()
+        .to_rfc3339()
+}
+
+#[test]
+fn zoned_input() {
+    let dates = parser().with_zone(Europe::Berlin);
+    let resolve = |date: &str| {
+        let (resolved, zone) = dates.resolve(date, None).unwrap();
+        (resolved.to_rfc3339(), zone)
+    };
+
+    assert_eq!(resolve("2024-05-01 09:00"), (utc("2024-05-01 07:00"), None));
+    assert_eq!(
+        resolve("2024-05-01 09:00 America/New_York"),
+        (utc("2024-05-01 13:00"), Some(America::New_York))
+    );
+    assert_eq!(
+        resolve("2024-05-01T09:00:00+02:00"),
+        (utc("2024-05-01 07:00"), None)
+    );
+    // skipped when the clocks go forward, repeated when they go back
+    assert_eq!(resolve("2024-03-31 02:30"), (utc("2024-03-31 01:30"), None));
+    assert_eq!(resolve("2024-10-27 02:30"), (utc("2024-10-27 00:30"), None));
+
+    // an existing zone is kept for dates without one
+    let (resolved, zone) = dates
+        .resolve("2024-05-01 09:00", Some(America::New_York))
+        .unwrap();
+    assert_eq!(
+        (resolved.to_rfc3339(), zone),
+        (utc("2024-05-01 13:00"), Some(America::New_York))
+    );
+
+    assert!(matches!(
+        dates.resolve("2024-05-01 09:00 Mars/Base", None),
+        Err(TaskError::TaskZoneParseError(zone)) if zone == "Mars/Base"
+    ));
+}
+
+#[test]
+fn zoned_relative_dates() {
+    // already the next day in Moscow
+    let dates =
+        DateParser::new(FixedClock(date("2024-05-15 22:00").and_utc())).with_zone(Europe::Moscow);
+    assert_eq!(dates.parse("today").unwrap(), date("2024-05-16 00:00"));
+    assert_eq!(dates.canonicalize("today").unwrap(), "2024-05-15 21:00:00");
+    assert_eq!(
+        dates.format(date("2024-05-15 22:00").and_utc(), None),
+        "2024-05-16 01:00"
+    );
+    assert_eq!(
+        dates.format(date("2024-05-15 22:00").and_utc(), Some(America::New_York)),
+        "2024-05-15 18:00"
+    );
+}
@@ -1,4 +1,7 @@
-use crate::back::expression::parse_args;
+use crate::back::errors::{ExpressionError, QueryError};
+use crate::back::expression::{
+    parse_args, parse_query, DateBucket, Expr, GroupBy, LeftVar, Op, Projection, SortOrder,
+};
 
 #[test]
 pub fn parse_expr() {
@@ -97,3 +100,338 @@ pub fn parse_error_gibberish_with_like() {
     let parsed = parse_args(args);
     assert!(parsed.is_err());
 }
+
+#[test]
+pub fn parse_or() {
+    let parsed = parse_args("category=work or category=home");
+    assert!(matches!(parsed, Ok(Expr::Or(_, _))));
+}
+
+#[test]
+pub fn parse_and_binds_tighter_than_or() {
+    let parsed = parse_args("name=a or name=b and status=true").unwrap();
+    let Expr::Or(left, right) = parsed else {
+        panic!("expected or at the top, got {:?}", parsed);
+    };
+    assert!(matches!(
+        *left,
+        Expr::Comparison(LeftVar::Name, Op::Equals, _)
+    ));
+    assert!(matches!(*right, Expr::And(_, _)));
+}
+
+#[test]
+pub fn parse_parens_group() {
+    let parsed = parse_args("(name=a or name=b) and status=true").unwrap();
+    let Expr::And(left, _) = parsed else {
+        panic!("expected and at the top, got {:?}", parsed);
+    };
+    assert!(matches!(*left, Expr::Or(_, _)));
+}
+
+#[test]
+pub fn parse_not() {
+    let parsed = parse_args("not status=true and not (name=a or name=b)").unwrap();
+    let Expr::And(left, right) = parsed else {
+        panic!("expected and at the top, got {:?}", parsed);
+    };
+    assert!(matches!(*left, Expr::Not(_)));
+    assert!(matches!(*right, Expr::Not(_)));
+}
+
+#[test]
+pub fn parse_multi_word_value() {
+    let parsed = parse_args("date=2015-09-05 00:00 or name=qwe").unwrap();
+    let Expr::Or(left, _) = parsed else {
+        panic!("expected or at the top, got {:?}", parsed);
+    };
+    assert!(
+        matches!(*left, Expr::Comparison(LeftVar::Date, Op::Equals, value) if value == "2015-09-05 00:00")
+    );
+}
+
+#[test]
+pub fn parse_error_unbalanced_parens() {
+    assert!(matches!(
+        parse_args("(name=a or name=b"),
+        Err(QueryError {
+            kind: ExpressionError::UnbalancedParensError,
+            ..
+        })
+    ));
+    assert!(matches!(
+        parse_args("name=a or name=b)"),
+        Err(QueryError {
+            kind: ExpressionError::UnbalancedParensError,
+            ..
+        })
+    ));
+}
+
+#[test]
+pub fn parse_error_dangling_operator() {
+    assert!(parse_args("name=a or").is_err());
+    assert!(parse_args("not").is_err());
+    assert!(parse_args("name=a and or name=b").is_err());
+}
+
+#[test]
+pub fn parse_keywords_inside_quotes() {
+    let parsed = parse_args("description=\"band practice\"").unwrap();
+    assert!(
+        matches!(parsed, Expr::Comparison(LeftVar::Description, Op::Equals, value) if value == "band practice")
+    );
+
+    let parsed = parse_args("name=\"not (a or b) and c\" and status=true").unwrap();
+    let Expr::And(left, _) = parsed else {
+        panic!("expected and at the top, got {:?}", parsed);
+    };
+    assert!(
+        matches!(*left, Expr::Comparison(LeftVar::Name, Op::Equals, value) if value == "not (a or b) and c")
+    );
+}
+
+#[test]
+pub fn parse_keywords_inside_words() {
+    let parsed = parse_args("name=unlikely").unwrap();
+    assert!(
+        matches!(parsed, Expr::Comparison(LeftVar::Name, Op::Equals, value) if value == "unlikely")
+    );
+
+    let parsed = parse_args("category=brand and name like band").unwrap();
+    assert!(matches!(parsed, Expr::And(_, _)));
+}
+
+#[test]
+pub fn parse_operators_inside_quotes() {
+    let parsed = parse_args("name like \"a<=b!=c\"").unwrap();
+    assert!(
+        matches!(parsed, Expr::Comparison(LeftVar::Name, Op::Like, value) if value == "a<=b!=c")
+    );
+}
+
+#[test]
+pub fn parse_escapes() {
+    let parsed = parse_args(r#"description="say \"hi\" \\o/""#).unwrap();
+    assert!(
+        matches!(parsed, Expr::Comparison(LeftVar::Description, Op::Equals, value) if value == r#"say "hi" \o/"#)
+    );
+}
+
+#[test]
+pub fn parse_error_unterminated_string() {
+    assert!(matches!(
+        parse_args("name=\"qwe"),
+        Err(QueryError {
+            kind: ExpressionError::UnterminatedStringError,
+            ..
+        })
+    ));
+    assert!(matches!(
+        parse_args("name=\"qwe\\\""),
+        Err(QueryError {
+            kind: ExpressionError::UnterminatedStringError,
+            ..
+        })
+    ));
+}
+
+#[test]
+pub fn parse_error_quoted_field() {
+    assert!(parse_args("\"name\"=qwe").is_err());
+}
+
+#[test]
+pub fn parse_query_clauses() {
+    let query =
+        parse_query("select * where name=a order by date desc, name asc, status limit 10 offset 5")
+            .unwrap();
+    assert!(query.filter.is_some());
+    assert_eq!(
+        query.order_by,
+        [
+            (LeftVar::Date, SortOrder::Desc),
+            (LeftVar::Name, SortOrder::Asc),
+            (LeftVar::Status, SortOrder::Asc)
+        ]
+    );
+    assert_eq!(query.limit, Some(10));
+    assert_eq!(query.offset, 5);
+}
+
+#[test]
+pub fn parse_query_clause_ends_unquoted_value() {
+    let query = parse_query("select * where date=2015-09-05 00:00 limit 1").unwrap();
+    assert!(matches!(
+        query.filter,
+        Some(Expr::Comparison(LeftVar::Date, Op::Equals, value)) if value == "2015-09-05 00:00"
+    ));
+    assert_eq!(query.limit, Some(1));
+
+    let query = parse_query("select * where name=\"order by\" order by name").unwrap();
+    assert!(matches!(
+        query.filter,
+        Some(Expr::Comparison(LeftVar::Name, Op::Equals, value)) if value == "order by"
+    ));
+}
+
+#[test]
+pub fn parse_query_without_filter() {
+    let query = parse_query("select * order by name").unwrap();
+    assert!(query.filter.is_none());
+    assert_eq!(query.order_by.len(), 1);
+}
+
+#[test]
+pub fn parse_query_errors() {
+    assert!(parse_query("select * order by stat").is_err());
+    assert!(parse_query("select * order by").is_err());
+    assert!(parse_query("select * order date").is_err());
+    assert!(matches!(
+        parse_query("select * limit ten"),
+        Err(QueryError {
+            kind: ExpressionError::NumberParseError,
+            ..
+        })
+    ));
+    assert!(parse_query("select * offset 1 limit 1").is_err());
+    assert!(parse_query("select * where").is_err());
+}
+
+#[test]
+pub fn parse_query_projection() {
+    let query = parse_query("select name, date where status=false").unwrap();
+    assert_eq!(
+        query.projection,
+        Projection::Columns(vec![LeftVar::Name, LeftVar::Date])
+    );
+    assert!(query.filter.is_some());
+
+    let query = parse_query("select *").unwrap();
+    assert_eq!(query.projection, Projection::All);
+    assert_eq!(query.projection.columns(), LeftVar::ALL);
+}
+
+#[test]
+pub fn parse_query_unknown_column() {
+    assert!(matches!(
+        parse_query("select name, stat where name=a"),
+        Err(QueryError { kind: ExpressionError::UnknownColumnError(column), .. }) if column == "stat"
+    ));
+    assert!(parse_query("select name,").is_err());
+    assert!(parse_query("select").is_err());
+}
+
+#[test]
+pub fn parse_query_count() {
+    let query = parse_query("select count(*) where status=false group by category").unwrap();
+    assert_eq!(query.projection, Projection::Count);
+    assert_eq!(query.group_by, Some(GroupBy::Field(LeftVar::Category)));
+
+    let query = parse_query("select count(*) group by date week").unwrap();
+    assert_eq!(query.group_by, Some(GroupBy::Date(DateBucket::Week)));
+
+    let query = parse_query("select count(*) where date>2015-09-05 00:00 group by date").unwrap();
+    assert_eq!(query.group_by, Some(GroupBy::Date(DateBucket::Day)));
+}
+
+#[test]
+pub fn parse_query_count_errors() {
+    assert!(matches!(
+        parse_query("select * group by category"),
+        Err(QueryError {
+            kind: ExpressionError::GroupByWithoutCountError,
+            ..
+        })
+    ));
+    assert!(parse_query("select count(name)").is_err());
+    assert!(parse_query("select count(*) group by").is_err());
+    assert!(parse_query("select count(*) group by date year").is_err());
+}
+
+#[test]
+pub fn parse_status_values() {
+    let value = |args: &str| match parse_args(args).unwrap() {
+        Expr::Comparison(LeftVar::Status, _, value) => value,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(value("status=True"), "true");
+    assert_eq!(value("status=1"), "true");
+    assert_eq!(value("status=\"on\""), "true");
+    assert_eq!(value("status=No"), "false");
+    assert_eq!(value("status=\"In Progress\""), "in-progress");
+    assert_eq!(value("status!=canceled"), "cancelled");
+    assert_eq!(value("status like PROG"), "prog");
+    // text fields are compared as typed
+    assert!(matches!(
+        parse_args("name=True").unwrap(),
+        Expr::Comparison(LeftVar::Name, Op::Equals, value) if value == "True"
+    ));
+
+    assert!(matches!(
+        parse_args("status=maybe"),
+        Err(QueryError { kind: ExpressionError::ValueTypeError(LeftVar::Status, value), .. }) if value == "maybe"
+    ));
+}
+
+#[test]
+pub fn parse_bool_and_date_values() {
+    let value = |args: &str| match parse_args(args).unwrap() {
+        Expr::Comparison(_, _, value) => value,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(value("is_blocked=yes"), "true");
+    assert_eq!(value("is_blocked=1"), "true");
+    assert_eq!(value("is_blocked!=Off"), "false");
+    assert!(matches!(
+        parse_args("is_blocked=maybe"),
+        Err(QueryError { kind: ExpressionError::ValueTypeError(LeftVar::IsBlocked, value), .. }) if value == "maybe"
+    ));
+
+    assert_eq!(value("date<2024-05-01 09:00"), "2024-05-01 09:00");
+    assert!(matches!(
+        parse_args("date<2024-13-01 09:00"),
+        Err(QueryError { kind: ExpressionError::ValueTypeError(LeftVar::Date, value), .. }) if value == "2024-13-01 09:00"
+    ));
+}
+
+#[test]
+pub fn query_errors_point_at_the_token() {
+    let error = parse_query("select * where stat=done").unwrap_err();
+    assert!(matches!(&error.kind, ExpressionError::UnknownFieldError(field) if field == "stat"));
+    assert_eq!(error.span, 15..19);
+    assert_eq!(
+        error.to_string(),
+        "unknown field 'stat', did you mean 'status'?\n  |\n  | select * where stat=done\n  |                ^^^^"
+    );
+
+    let error = parse_query("select * where name=a and").unwrap_err();
+    assert!(matches!(error.kind, ExpressionError::UnexpectedEndError));
+    assert_eq!(error.span, 25..25);
+
+    let error = parse_query("select * where (name=a or name=b").unwrap_err();
+    assert!(matches!(error.kind, ExpressionError::UnbalancedParensError));
+    assert_eq!(error.span, 15..16);
+
+    let error = parse_query("select * where status = don order by date").unwrap_err();
+    assert_eq!(error.span, 24..27);
+    assert!(error.to_string().contains("did you mean 'done'?"));
+
+    // carets line up with chars rather than bytes
+    let error = parse_query("select * where name=\"задача\" and категория=дом").unwrap_err();
+    assert_eq!(
+        error.to_string().lines().last().unwrap(),
+        format!("  | {}^^^^^^^^^", " ".repeat(33))
+    );
+}
+
+#[test]
+pub fn unknown_field_without_a_close_match() {
+    let error = parse_args("zzzzzz=a").unwrap_err();
+    assert_eq!(error.kind.to_string(), "unknown field 'zzzzzz'");
+    let error = parse_query("select nmae, date").unwrap_err();
+    assert_eq!(
+        error.kind.to_string(),
+        "unknown column 'nmae', did you mean 'name'?"
+    );
+}
@@ -1,4 +1,7 @@
 #![cfg(test)]
+mod dates_tests;
 mod expression_tests;
+mod recurrence_tests;
+mod storage_tests;
 mod task_manager_tests;
 mod task_tests;
@@ -0,0 +1,205 @@
+use crate::back::dates::DateParser;
+use crate::back::errors::TaskError;
+use crate::back::recurrence::{Frequency, Recurrence};
+use crate::back::task_model::{Status, Task};
+use chrono::{NaiveDateTime, Weekday};
+use chrono_tz::{Europe, Tz};
+
+fn date(date: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap()
+}
+
+fn rule(rule: &str) -> Recurrence {
+    Recurrence::parse(rule, &DateParser::default(), None).unwrap()
+}
+
+#[test]
+fn parse_rules() {
+    assert_eq!(
+        rule("weekly"),
+        Recurrence {
+            frequency: Frequency::Weekly,
+            interval: 1,
+            weekdays: Vec::new(),
+            until: None,
+            count: None,
+            day: None,
+        }
+    );
+
+    let parsed = rule("Every 2 weeks on mon, fri until 2024-12-31 00:00 count 10");
+    assert_eq!(parsed.frequency, Frequency::Weekly);
+    assert_eq!(parsed.interval, 2);
+    assert_eq!(parsed.weekdays, [Weekday::Mon, Weekday::Fri]);
+    assert_eq!(parsed.until, Some(date("2024-12-31 00:00").and_utc()));
+    assert_eq!(parsed.count, Some(10));
+
+    assert_eq!(rule("every 3 months").frequency, Frequency::Monthly);
+    assert_eq!(rule("every day count 5").count, Some(5));
+}
+
+#[test]
+fn parse_rule_errors() {
+    for invalid in [
+        "",
+        "sometimes",
+        "every",
+        "every 0 days",
+        "every 2 fortnights",
+        "monthly on mon",
+        "every 7 days on mon,wed",
+        "every 14 days on fri",
+        "weekly on funday",
+        "weekly count 0",
+        "weekly count",
+        "weekly until",
+        "weekly count 3 until 2024-12-31",
+    ] {
+        assert!(
+            Recurrence::parse(invalid, &DateParser::default(), None).is_err(),
+            "{} should not parse",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn display_reads_back() {
+    for text in [
+        "every day",
+        "every 2 weeks on mon,fri",
+        "every month until 2024-12-31 09:00 UTC count 3",
+    ] {
+        let parsed = rule(text);
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(rule(&parsed.to_string()), parsed);
+    }
+}
+
+#[test]
+fn next_occurrence() {
+    // a Wednesday
+    let start = date("2024-05-15 09:00");
+    assert_eq!(rule("daily").next(start), Some(date("2024-05-16 09:00")));
+    assert_eq!(
+        rule("every 3 days").next(start),
+        Some(date("2024-05-18 09:00"))
+    );
+    assert_eq!(rule("weekly").next(start), Some(date("2024-05-22 09:00")));
+    assert_eq!(
+        rule("weekly on mon,fri").next(start),
+        Some(date("2024-05-17 09:00"))
+    );
+    assert_eq!(
+        rule("every 2 weeks on mon,wed").next(start),
+        Some(date("2024-05-27 09:00"))
+    );
+    assert_eq!(
+        rule("daily on mon,tue,wed,thu,fri").next(date("2024-05-17 09:00")),
+        Some(date("2024-05-20 09:00"))
+    );
+    assert_eq!(
+        rule("monthly").next(date("2024-01-31 09:00")),
+        Some(date("2024-02-29 09:00"))
+    );
+    assert_eq!(rule("yearly").next(start), Some(date("2025-05-15 09:00")));
+}
+
+#[test]
+fn task_next_occurrence() {
+    let task = Task::new("water plants; ; 2024-05-15 09:00; home; repeat=weekly count 2").unwrap();
+    let next = task.next_occurrence(Tz::UTC).unwrap();
+    assert_eq!(next.date, date("2024-05-22 09:00").and_utc());
+    assert_eq!(next.recurrence.as_ref().unwrap().count, Some(1));
+    assert_eq!(next.status, Status::Todo);
+    // the count is used up
+    assert!(next.next_occurrence(Tz::UTC).is_none());
+
+    let until =
+        Task::new("qwe; ; 2024-05-15 09:00; home; repeat=daily until 2024-05-16 08:00").unwrap();
+    assert!(until.next_occurrence(Tz::UTC).is_none());
+
+    let once = Task::new("qwe; ; 2024-05-15 09:00; home").unwrap();
+    assert!(once.next_occurrence(Tz::UTC).is_none());
+}
+
+#[test]
+fn occurrences_keep_local_time_across_dst() {
+    // Berlin switches to summer time on 2024-03-31
+    let task = Task::new("qwe; ; 2024-03-30 09:00 Europe/Berlin; home; repeat=daily").unwrap();
+    let next = task.next_occurrence(Tz::UTC).unwrap();
+    assert_eq!(
+        next.date.with_timezone(&Europe::Berlin).naive_local(),
+        date("2024-03-31 09:00")
+    );
+    assert_eq!(next.date, date("2024-03-31 07:00").and_utc());
+}
+
+#[test]
+fn unknown_option() {
+    assert!(matches!(
+        Task::new("qwe; ; 2024-05-15 09:00; home; colour=red"),
+        Err(TaskError::TaskOptionError(option)) if option == "colour=red"
+    ));
+    assert!(matches!(
+        Task::new("qwe; ; 2024-05-15 09:00; home; repeat=sometimes"),
+        Err(TaskError::RecurrenceParseError(_))
+    ));
+}
+
+#[test]
+fn clamped_days_come_back() {
+    let occurrences = |task: &str, n: usize| -> Vec<NaiveDateTime> {
+        let mut task = Task::new(task).unwrap();
+        let mut dates = Vec::new();
+        for _ in 0..n {
+            task = task.next_occurrence(Tz::UTC).unwrap();
+            dates.push(task.date.naive_utc());
+        }
+        dates
+    };
+
+    assert_eq!(
+        occurrences("rent; ; 2024-01-31 09:00; home; repeat=monthly", 3),
+        [
+            date("2024-02-29 09:00"),
+            date("2024-03-31 09:00"),
+            date("2024-04-30 09:00")
+        ]
+    );
+    assert_eq!(
+        occurrences("leap; ; 2024-02-29 09:00; home; repeat=yearly", 4),
+        [
+            date("2025-02-28 09:00"),
+            date("2026-02-28 09:00"),
+            date("2027-02-28 09:00"),
+            date("2028-02-29 09:00")
+        ]
+    );
+}
+
+#[test]
+fn until_without_time_takes_the_whole_day() {
+    let parsed = rule("daily until 2024-05-16");
+    assert_eq!(
+        parsed.until,
+        Some(date("2024-05-16 23:59").and_utc() + chrono::Duration::seconds(59))
+    );
+    assert_eq!(rule(&parsed.to_string()), parsed);
+
+    let task = Task::new("qwe; ; 2024-05-15 09:00; home; repeat=daily until 2024-05-16").unwrap();
+    let next = task.next_occurrence(Tz::UTC).unwrap();
+    assert_eq!(next.date, date("2024-05-16 09:00").and_utc());
+    assert!(next.next_occurrence(Tz::UTC).is_none());
+
+    let berlin = Recurrence::parse(
+        "daily until 2024-05-16 Europe/Berlin",
+        &DateParser::default(),
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        berlin.until,
+        Some(date("2024-05-16 21:59").and_utc() + chrono::Duration::seconds(59))
+    );
+}
@@ -0,0 +1,402 @@
+#![allow(unused_must_use)]
+use crate::back::errors::{StorageError, TaskMgrError};
+use crate::back::expression::parse_args;
+use crate::back::storage::{
+    JournalStorage, JsonFileStorage, MemoryStorage, SqliteStorage, Storage,
+};
+use crate::back::task_manager::TaskManager;
+use crate::back::task_model::{Priority, Status, Task};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+fn exercise_storage(storage: &mut dyn Storage) {
+    let qwe = storage
+        .insert(Task::new("qwe; rty; 2015-09-05 00:00; fgh").unwrap())
+        .unwrap();
+    let zxc = storage
+        .insert(Task::new("zxc; asd; 2020-09-05 00:00; tyu").unwrap())
+        .unwrap();
+    assert_ne!(qwe, zxc);
+
+    let mut task = storage.find(qwe).unwrap().unwrap();
+    task.transition(Status::Done).unwrap();
+    task.parent = Some(zxc);
+    task.blocked_by.insert(zxc);
+    storage.update(task).unwrap();
+
+    let removed = storage.remove(zxc).unwrap();
+    assert_eq!(removed.name, "zxc");
+
+    // ids of removed tasks are never reused
+    let new = storage
+        .insert(Task::new("zxc; asd; 2020-09-05 00:00; tyu").unwrap())
+        .unwrap();
+    assert!(new > zxc);
+    storage.remove(new).unwrap();
+
+    let tasks = storage.list().unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].id, qwe);
+    assert_eq!(tasks[0].status, Status::Done);
+    assert_eq!(tasks[0].parent, Some(zxc));
+    assert_eq!(tasks[0].blocked_by, [zxc].into());
+}
+
+#[test]
+fn memory_storage() {
+    exercise_storage(&mut MemoryStorage::new());
+}
+
+#[test]
+fn json_file_storage() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.json");
+    exercise_storage(&mut JsonFileStorage::open(&path).unwrap());
+
+    let mut reopened = JsonFileStorage::open(&path).unwrap();
+    assert_eq!(reopened.list().unwrap().len(), 1);
+    let id = reopened
+        .insert(Task::new("asd; fgh; 2020-09-05 00:00; tyu").unwrap())
+        .unwrap();
+    assert_eq!(id, 4);
+}
+
+#[test]
+fn json_file_without_ids() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.json");
+    let task = r#"{"name": "qwe", "description": "rty", "date": "2015-09-05T00:00:00", "category": "fgh", "done": false}"#;
+    fs::write(&path, format!(r#"{{"tasks": [{}, {}]}}"#, task, task)).unwrap();
+
+    let storage = JsonFileStorage::open(&path).unwrap();
+    let ids: Vec<u64> = storage.list().unwrap().iter().map(|t| t.id).collect();
+    assert_eq!(ids, vec![1, 2]);
+    // dates written before they had zones are read as UTC
+    let task = storage.find(1).unwrap().unwrap();
+    assert_eq!(task.date.to_rfc3339(), "2015-09-05T00:00:00+00:00");
+    assert_eq!(task.zone, None);
+}
+
+#[test]
+fn zones_persist() {
+    let dir = tempfile::tempdir().unwrap();
+    let zoned = Task::new("qwe; rty; 2024-05-01 09:00 Europe/Berlin; fgh").unwrap();
+    let storages: Vec<Box<dyn Storage>> = vec![
+        Box::new(JsonFileStorage::open(dir.path().join("tasks.json")).unwrap()),
+        Box::new(JournalStorage::open(dir.path().join("journal.json")).unwrap()),
+        Box::new(SqliteStorage::open(dir.path().join("tasks.db")).unwrap()),
+    ];
+    for mut storage in storages {
+        storage.insert(zoned.clone()).unwrap();
+    }
+
+    let reopened: Vec<Box<dyn Storage>> = vec![
+        Box::new(JsonFileStorage::open(dir.path().join("tasks.json")).unwrap()),
+        Box::new(JournalStorage::open(dir.path().join("journal.json")).unwrap()),
+        Box::new(SqliteStorage::open(dir.path().join("tasks.db")).unwrap()),
+    ];
+    for storage in reopened {
+        let task = storage.find(1).unwrap().unwrap();
+        assert_eq!(task.date, zoned.date);
+        assert_eq!(task.zone, Some(chrono_tz::Europe::Berlin));
+    }
+}
+
+#[test]
+fn sqlite_adds_missing_columns() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.db");
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE tasks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                description TEXT NOT NULL,
+                date TEXT NOT NULL,
+                category TEXT NOT NULL,
+                done INTEGER NOT NULL
+            );
+            INSERT INTO tasks (name, description, date, category, done)
+            VALUES ('qwe', 'rty', '2015-09-05 00:00:00', 'fgh', 0),
+                ('asd', 'rty', '2015-09-05 00:00:00', 'fgh', 1);",
+        )
+        .unwrap();
+    drop(connection);
+
+    let storage = SqliteStorage::open(&path).unwrap();
+    let task = storage.find(1).unwrap().unwrap();
+    assert_eq!(task.name, "qwe");
+    assert_eq!(task.zone, None);
+    assert_eq!(task.status, Status::Todo);
+    assert_eq!(task.priority, Priority::Normal);
+    assert!(task.tags.is_empty());
+    assert_eq!(task.parent, None);
+    assert!(task.blocked_by.is_empty());
+    assert_eq!(storage.find(2).unwrap().unwrap().status, Status::Done);
+}
+
+#[test]
+fn update_missing_task() {
+    let mut storage = MemoryStorage::new();
+    let mut task = Task::new("qwe; rty; 2015-09-05 00:00; fgh").unwrap();
+    task.id = 42;
+    let result = storage.update(task);
+    assert!(matches!(result, Err(TaskMgrError::TaskNotFound(task_ref)) if task_ref == "#42"));
+}
+
+#[test]
+fn boxed_storage_in_manager() {
+    let storage: Box<dyn Storage> = Box::new(MemoryStorage::new());
+    let mut mgr = TaskManager::with_storage(storage);
+    mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh");
+    let result = mgr.exec_command("select * where name=qwe");
+    assert!(result.is_ok());
+}
+
+#[test]
+fn journal_storage() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.json");
+    exercise_storage(&mut JournalStorage::open(&path).unwrap());
+
+    let mut reopened = JournalStorage::open(&path).unwrap();
+    let tasks = reopened.list().unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].status, Status::Done);
+    let id = reopened
+        .insert(Task::new("asd; fgh; 2020-09-05 00:00; tyu").unwrap())
+        .unwrap();
+    assert_eq!(id, 4);
+}
+
+#[test]
+fn journal_torn_record_is_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.json");
+    let mut storage = JournalStorage::open(&path).unwrap();
+    storage.insert(Task::new("qwe; rty; 2015-09-05 00:00; fgh").unwrap());
+    let journal_path = storage.journal_path().to_path_buf();
+    drop(storage);
+
+    // a crash in the middle of writing the second record
+    let mut file = OpenOptions::new().append(true).open(&journal_path).unwrap();
+    file.write_all(b"0badc0de {\"seq\":2,\"entry\":{\"op\":\"ins")
+        .unwrap();
+    drop(file);
+
+    let mut reopened = JournalStorage::open(&path).unwrap();
+    assert_eq!(reopened.list().unwrap().len(), 1);
+
+    reopened.insert(Task::new("zxc; asd; 2020-09-05 00:00; tyu").unwrap());
+    let reopened = JournalStorage::open(&path).unwrap();
+    assert_eq!(reopened.list().unwrap().len(), 2);
+}
+
+#[test]
+fn journal_corrupted_record_in_the_middle() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.json");
+    let mut storage = JournalStorage::open(&path).unwrap();
+    storage.insert(Task::new("qwe; rty; 2015-09-05 00:00; fgh").unwrap());
+    storage.insert(Task::new("zxc; asd; 2020-09-05 00:00; tyu").unwrap());
+    let journal_path = storage.journal_path().to_path_buf();
+    drop(storage);
+
+    let contents = fs::read_to_string(&journal_path).unwrap();
+    fs::write(&journal_path, contents.replacen("qwe", "qwf", 1)).unwrap();
+
+    let result = JournalStorage::open(&path);
+    assert!(matches!(
+        result,
+        Err(TaskMgrError::StorageErrorProp(StorageError::FormatError(_)))
+    ));
+}
+
+#[test]
+fn journal_compaction() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.json");
+    let mut storage = JournalStorage::open(&path)
+        .unwrap()
+        .with_compaction_interval(2);
+    let qwe = storage
+        .insert(Task::new("qwe; rty; 2015-09-05 00:00; fgh").unwrap())
+        .unwrap();
+    storage.insert(Task::new("zxc; asd; 2020-09-05 00:00; tyu").unwrap());
+    storage.remove(qwe).unwrap();
+
+    let journal = fs::read_to_string(storage.journal_path()).unwrap();
+    assert_eq!(journal.lines().count(), 1);
+
+    let reopened = JournalStorage::open(&path).unwrap();
+    let tasks = reopened.list().unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].name, "zxc");
+}
+
+#[test]
+fn sqlite_storage() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.db");
+    exercise_storage(&mut SqliteStorage::open(&path).unwrap());
+
+    let reopened = SqliteStorage::open(&path).unwrap();
+    let tasks = reopened.list().unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(
+        tasks[0].date,
+        Task::new("a; b; 2015-09-05 00:00; c").unwrap().date
+    );
+}
+
+#[test]
+fn undo_puts_tasks_back_with_their_ids() {
+    let dir = tempfile::tempdir().unwrap();
+    let storages: [Box<dyn Storage>; 3] = [
+        Box::new(SqliteStorage::open_in_memory().unwrap()),
+        Box::new(JournalStorage::open(dir.path().join("tasks.json")).unwrap()),
+        Box::new(MemoryStorage::new()),
+    ];
+    for storage in storages {
+        let mut mgr = TaskManager::with_storage(storage);
+        mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh");
+        mgr.exec_command("add zxc; asd; 2020-09-05 00:00; tyu");
+        mgr.exec_command("delete qwe").unwrap();
+        mgr.exec_command("undo").unwrap();
+
+        let tasks = mgr.get_tasks().unwrap();
+        let names: Vec<&str> = tasks.iter().map(|task| task.name.as_str()).collect();
+        assert_eq!(names, ["qwe", "zxc"]);
+        assert_eq!(mgr.find("qwe").unwrap().id, 1);
+
+        mgr.exec_command("redo").unwrap();
+        assert!(mgr.find("qwe").is_err());
+    }
+}
+
+#[test]
+fn sqlite_select_matches_memory() {
+    let tasks = [
+        "task1; 100% done; 2015-09-05 00:00; cat1; tags=a, b",
+        "task2; desc_2; 2015-09-06 12:30; cat2; priority=urgent; tags=b",
+        "other; Desc3; 2020-01-01 00:00; cat1; priority=low; blocked_by=#2",
+        "last; desc_4; 2020-01-02 00:00; cat2; blocked_by=#1, #3",
+    ];
+    let mut memory = TaskManager::new();
+    let mut sqlite = TaskManager::with_storage(SqliteStorage::open_in_memory().unwrap());
+    for task in tasks {
+        memory.add(Task::new(task).unwrap());
+        sqlite.add(Task::new(task).unwrap());
+    }
+    memory.check_done("task2");
+    sqlite.check_done("task2");
+    memory.set_status("other", Status::InProgress);
+    sqlite.set_status("other", Status::InProgress);
+
+    let queries = [
+        "select * where name like task",
+        "select * where description like \"%\"",
+        "select * where description like _",
+        "select * where description like desc",
+        "select * where category=cat1 and date>2015-09-05 00:00",
+        "select * where date<=2015-09-06 12:30",
+        "select * where status=true",
+        "select * where status!=true",
+        "select * where name>task1",
+        "select * where category=cat2 or not (description like desc and date<2016-01-01 00:00)",
+        "select * where not status=true and (name=task1 or name=other)",
+        "select * where status=in-progress",
+        "select * where status<done",
+        "select * where status>=in-progress",
+        "select * where status like progress",
+        "select * where status!=todo",
+        "select * where priority>normal",
+        "select * where priority<=normal and status!=done",
+        "select * where priority like gen",
+        "select * where tags has b",
+        "select * where tags has \"a b\"",
+        "select * where tags any (a, c)",
+        "select * where tags all (a, b)",
+        "select * where tags all (b, b, c)",
+        "select * where not tags any (\"\")",
+        "select * where tags all (\" \")",
+        "select * where is_blocked=true",
+        "select * where is_blocked=no or name=task1",
+        "select * where not is_blocked=true and status!=done",
+    ];
+    for query in queries {
+        let names = |tasks: Vec<Task>| tasks.into_iter().map(|t| t.name).collect::<Vec<_>>();
+        assert_eq!(
+            names(memory.select(query).unwrap()),
+            names(sqlite.select(query).unwrap()),
+            "{}",
+            query
+        );
+    }
+}
+
+#[test]
+fn sqlite_selects_blocked_tasks() {
+    let mut storage = SqliteStorage::open_in_memory().unwrap();
+    let open = storage
+        .insert(Task::new("open; a; 2015-09-05 00:00; c").unwrap())
+        .unwrap();
+    let done = storage
+        .insert(Task::new("done; a; 2015-09-05 00:00; c").unwrap())
+        .unwrap();
+    let mut task = storage.find(done).unwrap().unwrap();
+    task.transition(Status::Done).unwrap();
+    storage.update(task).unwrap();
+    for (name, blocked_by) in [("waits", open), ("goes", done), ("orphan", 42)] {
+        let mut task = Task::new(&format!("{}; a; 2015-09-05 00:00; c", name)).unwrap();
+        task.blocked_by.insert(blocked_by);
+        storage.insert(task).unwrap();
+    }
+
+    let names = |query: &str| -> Vec<String> {
+        let filter = parse_args(query).unwrap();
+        let tasks = storage.select(&filter).unwrap();
+        tasks.into_iter().map(|task| task.name).collect()
+    };
+    assert_eq!(names("is_blocked=true"), ["waits"]);
+    assert_eq!(
+        names("is_blocked!=true"),
+        ["open", "done", "goes", "orphan"]
+    );
+}
+
+#[test]
+fn date_filters_keep_seconds() {
+    let storages: [Box<dyn Storage>; 2] = [
+        Box::new(MemoryStorage::new()),
+        Box::new(SqliteStorage::open_in_memory().unwrap()),
+    ];
+    let mut managers = storages.map(TaskManager::with_storage);
+    for mgr in &mut managers {
+        mgr.exec_command("add a; d; 2024-05-01T09:00:30; misc")
+            .unwrap();
+        mgr.exec_command("add b; d; 2024-05-01T09:00:50.5; misc")
+            .unwrap();
+    }
+
+    let queries = [
+        ("select * where date = 2024-05-01T09:00:30", vec!["a"]),
+        ("select * where date < 2024-05-01T09:00:45", vec!["a"]),
+        ("select * where date >= 2024-05-01T09:00:10", vec!["a", "b"]),
+        ("select * where date > 2024-05-01 09:00:30", vec!["b"]),
+        ("select * where date = 2024-05-01 09:00:50", vec!["b"]),
+        ("select * where date <= 2024-05-01 09:00", vec![]),
+    ];
+    for (query, expected) in queries {
+        for mgr in &managers {
+            let names: Vec<String> = mgr
+                .select(query)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.name)
+                .collect();
+            assert_eq!(names, expected, "{}", query);
+        }
+    }
+}
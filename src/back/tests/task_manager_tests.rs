@@ -1,5 +1,13 @@
 #![allow(unused_must_use)]
-use crate::{back::task_manager::TaskManager, back::task_model::Task};
+use crate::back::dates::{DateParser, FixedClock};
+use crate::back::errors::{ExpressionError, QueryError, StorageError, TaskError, TaskMgrError};
+use crate::back::expression::LeftVar;
+use crate::back::rows::{QueryResult, Value};
+use crate::back::storage::MemoryStorage;
+use crate::back::task_model::{Priority, Status};
+use crate::{back::task_manager::TaskManager, back::task_model::Task, compose_update};
+use chrono::{NaiveDateTime, NaiveTime};
+use chrono_tz::{America, Europe};
 
 #[test]
 fn new_task_added() {
@@ -25,7 +33,7 @@ fn check_done() {
     mgr.add(task);
     mgr.check_done("qwe");
     let task = mgr.find("qwe").unwrap();
-    assert!(task.done);
+    assert_eq!(task.status, Status::Done);
 }
 
 #[test]
@@ -139,7 +147,7 @@ fn select_unwrap() {
     let query = "select * where name=qwe".to_string();
     let result = mgr.select(&query).unwrap();
 
-    assert_eq!(result[0].name, mgr.get_tasks()[0].name);
+    assert_eq!(result[0].name, mgr.get_tasks().unwrap()[0].name);
 }
 
 #[test]
@@ -204,6 +212,7 @@ fn select_from_multiple() {
 }
 
 #[test]
+#[allow(clippy::len_zero)]
 fn select_from_empty() {
     let mgr = TaskManager::new();
     let query = "select * where name=zxc".to_string();
@@ -302,11 +311,48 @@ fn command_done() {
     let add = "add qwe; rty; 2015-09-05 00:00; fgh";
     mgr.exec_command(add);
 
-    let before = mgr.find("qwe").unwrap().done;
-    mgr.exec_command("done qwe");
-    let after = mgr.find("qwe").unwrap().done;
+    assert_eq!(mgr.find("qwe").unwrap().status, Status::Todo);
+    assert!(mgr.exec_command("done qwe").is_ok());
+    assert_eq!(mgr.find("qwe").unwrap().status, Status::Done);
+
+    // a second `done` doesn't reopen the task
+    assert!(matches!(
+        mgr.exec_command("done qwe"),
+        Err(TaskMgrError::TaskUpdateErrorPropTask(
+            TaskError::IllegalTransitionError(Status::Done, Status::Done)
+        ))
+    ));
+    assert_eq!(mgr.find("qwe").unwrap().status, Status::Done);
+}
+
+#[test]
+fn command_status_transitions() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh");
+    mgr.exec_command("add asd; rty; 2015-09-05 00:00; fgh");
 
-    assert_ne!(before, after);
+    assert_eq!(
+        mgr.exec_command("start qwe").unwrap(),
+        "Task is in-progress now: #1 name: qwe, description: rty, date: 2015-09-05 00:00, category: fgh, status: in-progress, priority: normal, tags: , is_blocked: false"
+    );
+    mgr.exec_command("block qwe").unwrap();
+    assert!(mgr.exec_command("done qwe").is_err());
+    mgr.exec_command("cancel #2").unwrap();
+    assert!(mgr.exec_command("start #2").is_err());
+    // leading whitespace is fine
+    mgr.exec_command("  reopen #2").unwrap();
+
+    let names = |mgr: &TaskManager, query: &str| -> Vec<String> {
+        mgr.select(query)
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect()
+    };
+    assert_eq!(names(&mgr, "select * where status=blocked"), ["qwe"]);
+    assert_eq!(names(&mgr, "select * where status=todo"), ["asd"]);
+    assert_eq!(names(&mgr, "select * where status>in-progress"), ["qwe"]);
+    assert_eq!(names(&mgr, "select * order by status desc"), ["qwe", "asd"]);
 }
 
 #[test]
@@ -350,7 +396,7 @@ fn command_update_wrong_date() {
     let mut mgr = TaskManager::new();
     let add = "add qwe; rty; 2015-09-05 00:00; fgh";
     mgr.exec_command(add);
-    let result = mgr.exec_command("update qwe; zxc; lmao; 2022-09-02; chores");
+    let result = mgr.exec_command("update qwe; zxc; lmao; 2022-09-32; chores");
     assert!(result.is_err());
 }
 
@@ -410,3 +456,1112 @@ fn select_partial_match() {
     let result = mgr.select(&select).unwrap();
     assert_eq!(result.len(), 2);
 }
+
+#[test]
+fn load_missing_file_is_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let mgr = TaskManager::load(dir.path().join("tasks.json")).unwrap();
+    assert!(mgr.get_tasks().unwrap().is_empty());
+}
+
+#[test]
+fn tasks_persist_between_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.json");
+
+    let mut mgr = TaskManager::load(&path).unwrap();
+    mgr.exec_command("add qwe; rty; 2015-09-05 13:37; fgh");
+    mgr.exec_command("done qwe");
+
+    let reloaded = TaskManager::load(&path).unwrap();
+    let task = reloaded.find("qwe").unwrap();
+    assert_eq!(task.date, mgr.find("qwe").unwrap().date);
+    assert_eq!(task.status, Status::Done);
+}
+
+#[test]
+fn load_corrupted_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.json");
+    std::fs::write(&path, "{ not json").unwrap();
+    let result = TaskManager::load(&path);
+    assert!(matches!(
+        result,
+        Err(TaskMgrError::StorageErrorProp(StorageError::FormatError(_)))
+    ));
+}
+
+#[test]
+fn add_assigns_ids() {
+    let mut mgr = TaskManager::new();
+    let first = mgr.add(Task::new("qwe; rty; 2015-09-05 00:00; fgh").unwrap());
+    let second = mgr.add(Task::new("qwe; rty; 2015-09-05 00:00; fgh").unwrap());
+    assert_eq!((first.unwrap(), second.unwrap()), (1, 2));
+}
+
+#[test]
+fn find_by_id() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh");
+    mgr.exec_command("add zxc; asd; 2020-09-05 00:00; tyu");
+    assert_eq!(mgr.find("#2").unwrap().name, "zxc");
+    assert!(mgr.find("#3").is_err());
+}
+
+#[test]
+fn names_that_look_like_ids() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add #2; rty; 2015-09-05 00:00; fgh");
+    mgr.exec_command("add #7; asd; 2020-09-05 00:00; tyu");
+    mgr.exec_command("add #7; zxc; 2020-09-05 00:00; tyu");
+
+    // an id wins over a name
+    assert_eq!(mgr.find("#2").unwrap().name, "#7");
+    // no task has id 7, so it is a name, and a shared one
+    assert!(matches!(
+        mgr.find("#7"),
+        Err(TaskMgrError::AmbiguousTaskName(name, ids)) if name == "#7" && ids == vec![2, 3]
+    ));
+    mgr.exec_command("delete #3").unwrap();
+    assert_eq!(mgr.find("#7").unwrap().id, 2);
+}
+
+#[test]
+fn duplicate_names_are_ambiguous() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh");
+    mgr.exec_command("add qwe; asd; 2020-09-05 00:00; tyu");
+
+    let result = mgr.exec_command("delete qwe");
+    assert!(
+        matches!(result, Err(TaskMgrError::AmbiguousTaskName(name, ids)) if name == "qwe" && ids == vec![1, 2])
+    );
+    assert_eq!(mgr.get_tasks().unwrap().len(), 2);
+}
+
+#[test]
+fn commands_by_id() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh");
+    mgr.exec_command("add qwe; asd; 2020-09-05 00:00; tyu");
+
+    assert!(mgr.exec_command("done #2").is_ok());
+    assert_eq!(mgr.find("#2").unwrap().status, Status::Done);
+    assert!(mgr
+        .exec_command("update #1; zxc; lmao; 2022-09-05 00:00; chores")
+        .is_ok());
+    assert!(mgr.exec_command("delete #2").is_ok());
+    assert_eq!(mgr.find("zxc").unwrap().id, 1);
+    assert!(mgr.find("qwe").is_err());
+}
+
+#[test]
+fn select_boolean_grammar() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add task1; desc1; 2015-09-05 00:00; work");
+    mgr.exec_command("add task2; desc2; 2015-09-06 00:00; home");
+    mgr.exec_command("add task3; desc3; 2015-09-07 00:00; misc");
+    mgr.exec_command("done task2");
+
+    let names = |query: &str| -> Vec<String> {
+        mgr.select(query)
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect()
+    };
+
+    assert_eq!(
+        names("select * where category=work or category=home"),
+        ["task1", "task2"]
+    );
+    assert_eq!(names("select * where not status=true"), ["task1", "task3"]);
+    assert_eq!(
+        names("select * where not (category=work or category=home)"),
+        ["task3"]
+    );
+    assert_eq!(
+        names("select * where category=misc or category=home and status=false"),
+        ["task3"]
+    );
+    assert_eq!(names("select * where status=True"), ["task2"]);
+    assert_eq!(names("select * where status=\"on\""), ["task2"]);
+    assert_eq!(names("select * where status=0"), ["task1", "task3"]);
+    assert!(matches!(
+        mgr.select("select * where status=maybe"),
+        Err(TaskMgrError::WrongQueryPropExpr(QueryError {
+            kind: ExpressionError::ValueTypeError(LeftVar::Status, _),
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn select_quoted_keywords() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add unlikely; band practice; 2015-09-05 00:00; select * where");
+    mgr.exec_command("add other; desc; 2015-09-06 00:00; misc");
+
+    let found = |query: &str| mgr.select(query).unwrap().len();
+    assert_eq!(found("select * where name=\"unlikely\""), 1);
+    assert_eq!(found("select * where description=\"band practice\""), 1);
+    assert_eq!(found("select * where category=\"select * where\""), 1);
+    assert_eq!(found("select * where description like \"and\""), 1);
+}
+
+#[test]
+fn select_order_by() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add b; desc; 2015-09-05 00:00; work");
+    mgr.exec_command("add a; desc; 2015-09-05 00:00; work");
+    mgr.exec_command("add c; desc; 2016-01-01 00:00; home");
+    mgr.exec_command("done b");
+
+    let names = |query: &str| -> Vec<String> {
+        mgr.select(query)
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect()
+    };
+
+    assert_eq!(
+        names("select * order by date desc, name asc"),
+        ["c", "a", "b"]
+    );
+    assert_eq!(
+        names("select * order by status, name desc"),
+        ["c", "a", "b"]
+    );
+    assert_eq!(names("select * order by status desc"), ["b", "a", "c"]);
+    assert_eq!(
+        names("select * where category=work order by name"),
+        ["a", "b"]
+    );
+}
+
+#[test]
+fn select_limit_offset() {
+    let mut mgr = TaskManager::new();
+    for name in ["a", "b", "c", "d"] {
+        mgr.exec_command(&format!("add {}; desc; 2015-09-05 00:00; work", name));
+    }
+
+    let names = |query: &str| -> Vec<String> {
+        mgr.select(query)
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect()
+    };
+
+    assert_eq!(names("select * limit 2"), ["a", "b"]);
+    assert_eq!(names("select * limit 2 offset 3"), ["d"]);
+    assert_eq!(
+        names("select * order by name desc offset 1"),
+        ["c", "b", "a"]
+    );
+    assert!(names("select * offset 10").is_empty());
+}
+
+#[test]
+fn select_rows_projection() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh");
+    mgr.exec_command("add zxc; asd; 2020-09-05 00:00; tyu");
+
+    let rows = mgr
+        .select_rows("select name, date where category=tyu")
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].values.len(), 2);
+    assert_eq!(
+        rows[0].get(LeftVar::Name),
+        Some(&Value::Text("zxc".to_string()))
+    );
+    assert!(rows[0].get(LeftVar::Description).is_none());
+    assert_eq!(rows[0].to_string(), "name: zxc, date: 2020-09-05 00:00");
+
+    let rows = mgr.select_rows("select * order by name limit 1").unwrap();
+    assert_eq!(
+        rows[0].to_string(),
+        "#1 name: qwe, description: rty, date: 2015-09-05 00:00, category: fgh, status: todo, priority: normal, tags: , is_blocked: false"
+    );
+}
+
+#[test]
+fn command_select_unknown_column() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh");
+    let result = mgr.exec_command("select name, assignee");
+    assert!(matches!(
+        result,
+        Err(TaskMgrError::WrongQueryPropExpr(QueryError {
+            kind: ExpressionError::UnknownColumnError(_),
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn query_count() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add a; desc; 2024-04-30 10:00; work");
+    mgr.exec_command("add b; desc; 2024-05-01 10:00; work");
+    mgr.exec_command("add c; desc; 2024-05-01 18:00; home");
+    mgr.exec_command("add d; desc; 2024-05-13 09:00; home");
+    mgr.exec_command("done d");
+
+    let counts = |query: &str| -> Vec<String> {
+        match mgr.query(query).unwrap() {
+            QueryResult::Counts(counts) => counts.iter().map(|c| c.to_string()).collect(),
+            QueryResult::Rows(_) => panic!("expected counts for {}", query),
+        }
+    };
+
+    assert_eq!(counts("select count(*)"), ["count: 4"]);
+    assert_eq!(counts("select count(*) where name=zzz"), ["count: 0"]);
+    assert_eq!(
+        counts("select count(*) where status=false group by category"),
+        ["category: home, count: 1", "category: work, count: 2"]
+    );
+    assert_eq!(
+        counts("select count(*) group by status"),
+        ["status: todo, count: 3", "status: done, count: 1"]
+    );
+    assert_eq!(
+        counts("select count(*) group by date"),
+        [
+            "date: 2024-04-30, count: 1",
+            "date: 2024-05-01, count: 2",
+            "date: 2024-05-13, count: 1"
+        ]
+    );
+    assert_eq!(
+        counts("select count(*) group by date week"),
+        ["date: 2024-W18, count: 3", "date: 2024-W20, count: 1"]
+    );
+    assert_eq!(
+        counts("select count(*) group by date month limit 1 offset 1"),
+        ["date: 2024-05, count: 3"]
+    );
+}
+
+#[test]
+fn command_select_count() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add a; desc; 2024-04-30 10:00; work");
+    let result = mgr.exec_command("select count(*) group by category");
+    assert_eq!(result.unwrap(), "Selected:\ncategory: work, count: 1");
+}
+
+#[test]
+fn relative_dates() {
+    let now = NaiveDateTime::parse_from_str("2024-05-15 13:45", "%Y-%m-%d %H:%M").unwrap();
+    let mut mgr = TaskManager::new().with_dates(DateParser::new(FixedClock(now.and_utc())));
+    mgr.exec_command("add past; rty; -1d; fgh");
+    mgr.exec_command("add soon; rty; tomorrow 09:00; fgh");
+    mgr.exec_command("add later; rty; +2w; fgh");
+    mgr.exec_command("update past; past; rty; yesterday; fgh");
+
+    let past = mgr.find("past").unwrap();
+    assert_eq!(past.date.to_string(), "2024-05-14 00:00:00 UTC");
+    let soon = mgr.find("soon").unwrap();
+    assert_eq!(soon.date.to_string(), "2024-05-16 09:00:00 UTC");
+
+    let names = |query: &str| -> Vec<String> {
+        mgr.select(query)
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect()
+    };
+    assert_eq!(names("select * where date < today"), ["past"]);
+    assert_eq!(
+        names("select * where date >= today and date < +7d"),
+        ["soon"]
+    );
+    assert_eq!(
+        names("select * where date > end_of_week order by date"),
+        ["later"]
+    );
+    assert!(matches!(
+        mgr.select("select * where date < sometime"),
+        Err(TaskMgrError::WrongQueryPropExpr(QueryError {
+            kind: ExpressionError::ValueTypeError(LeftVar::Date, _),
+            span,
+            ..
+        })) if span == (22..30)
+    ));
+}
+
+#[test]
+fn command_previews_resolved_date() {
+    let now = NaiveDateTime::parse_from_str("2024-05-15 13:45", "%Y-%m-%d %H:%M").unwrap();
+    let mut mgr = TaskManager::new().with_dates(DateParser::new(FixedClock(now.and_utc())));
+
+    let added = mgr.exec_command("add report; write it; next friday 17:00; work");
+    assert_eq!(added.unwrap(), "Task added: #1, due 2024-05-17 17:00");
+
+    let updated = mgr.exec_command("update #1; report; write it; in 2 hours; work");
+    assert_eq!(updated.unwrap(), "Task updated, due 2024-05-15 15:45");
+}
+
+#[test]
+fn configured_date_formats() {
+    let now = NaiveDateTime::parse_from_str("2024-05-15 13:45", "%Y-%m-%d %H:%M").unwrap();
+    let dates = DateParser::new(FixedClock(now.and_utc()))
+        .with_input_formats(["%d/%m/%Y %H:%M", "%d/%m/%Y"])
+        .unwrap()
+        .with_default_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+        .with_display_format("%d.%m.%Y %H:%M")
+        .unwrap();
+    let mut mgr = TaskManager::new().with_dates(dates);
+
+    let added = mgr.exec_command("add qwe; rty; 01/05/2024; fgh");
+    assert_eq!(added.unwrap(), "Task added: #1, due 01.05.2024 09:00");
+    // the canonical format keeps working
+    assert!(mgr
+        .exec_command("add asd; rty; 2024-05-02 10:00; fgh")
+        .is_ok());
+    assert!(mgr.exec_command("add zxc; rty; 2024-05-02; fgh").is_err());
+
+    let selected = mgr.exec_command("select name, date where date < 02/05/2024");
+    assert_eq!(
+        selected.unwrap(),
+        "Selected:\nname: qwe, date: 01.05.2024 09:00"
+    );
+
+    let done = mgr.exec_command("done #2");
+    assert_eq!(
+        done.unwrap(),
+        "Task marked as done: #2 name: asd, description: rty, date: 02.05.2024 10:00, category: fgh, status: done, priority: normal, tags: , is_blocked: false"
+    );
+
+    assert!(matches!(
+        DateParser::default().with_display_format("%Y %Q"),
+        Err(TaskError::DateFormatError(_))
+    ));
+}
+
+#[test]
+fn zoned_dates() {
+    let mut mgr = TaskManager::new().with_dates(DateParser::default().with_zone(Europe::Berlin));
+    mgr.exec_command("add berlin; rty; 2024-05-01 09:00; fgh");
+    mgr.exec_command("add new_york; rty; 2024-05-01 09:00 America/New_York; fgh");
+    mgr.exec_command("add late; rty; 2024-05-02 01:30; fgh");
+
+    // 09:00 in New York is 15:00 in Berlin
+    let names = |query: &str| -> Vec<String> {
+        mgr.select(query)
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect()
+    };
+    assert_eq!(names("select * where date < 2024-05-01 12:00"), ["berlin"]);
+    assert_eq!(
+        names("select * where date > 2024-05-01 08:00 America/New_York order by date"),
+        ["new_york", "late"]
+    );
+
+    // every task is shown in its own zone
+    let selected = mgr.exec_command("select name, date order by date");
+    assert_eq!(
+        selected.unwrap(),
+        "Selected:\nname: berlin, date: 2024-05-01 09:00\nname: new_york, date: 2024-05-01 09:00\nname: late, date: 2024-05-02 01:30"
+    );
+
+    // 01:30 in Berlin is still the first of May in UTC, but the day buckets are local
+    let counted = mgr.exec_command("select count(*) group by date");
+    assert_eq!(
+        counted.unwrap(),
+        "Selected:\ndate: 2024-05-01, count: 2\ndate: 2024-05-02, count: 1"
+    );
+
+    // updating without a zone keeps the task's own
+    mgr.exec_command("update new_york; new_york; rty; 2024-05-02 10:00; fgh");
+    let task = mgr.find("new_york").unwrap();
+    assert_eq!(task.zone, Some(America::New_York));
+    assert_eq!(task.date.to_rfc3339(), "2024-05-02T14:00:00+00:00");
+}
+
+#[test]
+fn done_schedules_next_occurrence() {
+    let mut mgr = TaskManager::new();
+    let added =
+        mgr.exec_command("add chores; ; 2024-05-15 09:00; home; repeat=every 2 weeks count 2");
+    assert_eq!(
+        added.unwrap(),
+        "Task added: #1, due 2024-05-15 09:00, repeats every 2 weeks count 2"
+    );
+
+    let done = mgr.exec_command("done #1").unwrap();
+    assert!(done.ends_with("\nNext occurrence: #2, due 2024-05-29 09:00"));
+    assert!(mgr.find("#1").unwrap().recurrence.is_none());
+
+    let (_, next) = mgr.check_done("#2").unwrap();
+    assert!(next.is_none());
+    assert_eq!(mgr.get_tasks().unwrap().len(), 2);
+
+    // stop repeating on update
+    mgr.exec_command("add plants; ; 2024-05-15 09:00; home; repeat=daily");
+    mgr.exec_command("update plants; plants; ; 2024-05-15 09:00; home; repeat=none");
+    let (_, next) = mgr.check_done("plants").unwrap();
+    assert!(next.is_none());
+}
+
+#[test]
+fn repl_update_keeps_options() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add plants; ; 2024-05-15 09:00; home");
+
+    let fields = ["plants\n", "water\n", "2024-05-15 09:00\n", "home\n"];
+    let composed = compose_update("update plants; repeat=weekly\n", fields);
+    assert_eq!(
+        composed,
+        "update plants; plants; water; 2024-05-15 09:00; home; repeat=weekly"
+    );
+    mgr.exec_command(&composed).unwrap();
+    let task = mgr.find("plants").unwrap();
+    assert_eq!(task.description, "water");
+    assert_eq!(task.recurrence.unwrap().to_string(), "every week");
+
+    // without options only the prompted fields follow the task
+    assert_eq!(
+        compose_update("update #1\n", fields),
+        "update #1; plants; water; 2024-05-15 09:00; home"
+    );
+}
+
+#[test]
+fn error_messages() {
+    use std::error::Error;
+
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh");
+
+    let error = mgr.exec_command("done zxc").unwrap_err();
+    assert_eq!(error.to_string(), "no task matches 'zxc'");
+    assert!(error.source().is_none());
+
+    let error = mgr.exec_command("frobnicate qwe").unwrap_err();
+    assert_eq!(error.to_string(), "unknown command 'frobnicate'");
+
+    let error = mgr.exec_command(" \n").unwrap_err();
+    assert_eq!(error.to_string(), "no command given");
+
+    let error = TaskMgrError::GeneralTaskError(TaskError::TaskZoneParseError("Mars/Base".into()));
+    assert_eq!(
+        error.to_string(),
+        "invalid input: 'Mars/Base' is not an IANA time zone name"
+    );
+    assert!(error.source().is_none());
+
+    // leading whitespace is not part of the command
+    mgr.exec_command(" add asd; rty; 2022-01-01 00:00; fgh")
+        .unwrap();
+    mgr.exec_command("\tupdate asd; asd; qwe; 2022-01-01 00:00; fgh")
+        .unwrap();
+    mgr.exec_command("  done asd").unwrap();
+    mgr.exec_command(" delete asd").unwrap();
+    assert!(mgr.find("asd").is_err());
+
+    let error = mgr
+        .exec_command("add asd; rty; 2022-09-32; fgh")
+        .unwrap_err();
+    assert_eq!(error.to_string(), "could not add the task");
+    assert_eq!(
+        error.source().unwrap().to_string(),
+        "'2022-09-32' is not a date"
+    );
+
+    let error = mgr.exec_command("add asd; rty").unwrap_err();
+    assert_eq!(
+        error.source().unwrap().to_string(),
+        "expected name; description; date; category, got 2 field(s)"
+    );
+
+    mgr.exec_command("done qwe").unwrap();
+    let error = mgr.exec_command("done qwe").unwrap_err();
+    assert_eq!(error.to_string(), "could not update the task");
+    assert_eq!(
+        error.source().unwrap().to_string(),
+        "the task is already done"
+    );
+    let error = mgr.exec_command("start qwe").unwrap_err();
+    assert_eq!(
+        error.source().unwrap().to_string(),
+        "a done task cannot become in-progress"
+    );
+
+    let error = mgr.exec_command("select * where nam=qwe").unwrap_err();
+    assert_eq!(error.to_string(), "invalid query");
+    assert_eq!(
+        error.source().unwrap().to_string(),
+        "unknown field 'nam', did you mean 'name'?\n  |\n  | select * where nam=qwe\n  |                ^^^"
+    );
+}
+
+#[test]
+fn priorities() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add low; rty; 2015-09-05 00:00; fgh; priority=low");
+    mgr.exec_command("add normal; rty; 2015-09-06 00:00; fgh");
+    assert_eq!(
+        mgr.exec_command("add urgent; rty; 2015-09-07 00:00; fgh; priority=4")
+            .unwrap(),
+        "Task added: #3, due 2015-09-07 00:00, priority urgent"
+    );
+    mgr.exec_command("add high; rty; 2015-09-08 00:00; fgh; priority=High");
+    assert!(matches!(
+        mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh; priority=asap"),
+        Err(TaskMgrError::TaskCreationErrorProp(
+            TaskError::TaskOptionError(_)
+        ))
+    ));
+
+    let names = |mgr: &TaskManager, query: &str| -> Vec<String> {
+        mgr.select(query)
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect()
+    };
+    // the most urgent first unless ordered otherwise
+    assert_eq!(names(&mgr, "select *"), ["urgent", "high", "normal", "low"]);
+    assert_eq!(
+        names(&mgr, "select * order by date"),
+        ["low", "normal", "urgent", "high"]
+    );
+    assert_eq!(
+        names(&mgr, "select * order by priority, name"),
+        ["low", "normal", "high", "urgent"]
+    );
+    assert_eq!(
+        names(&mgr, "select * where priority>=high"),
+        ["urgent", "high"]
+    );
+    assert_eq!(names(&mgr, "select * where priority<2"), ["low"]);
+    assert_eq!(
+        names(&mgr, "select * where priority!=normal and priority<=high"),
+        ["high", "low"]
+    );
+    assert_eq!(names(&mgr, "select * where priority like rg"), ["urgent"]);
+    assert!(matches!(
+        mgr.select("select * where priority=asap"),
+        Err(TaskMgrError::WrongQueryPropExpr(QueryError {
+            kind: ExpressionError::ValueTypeError(LeftVar::Priority, _),
+            ..
+        }))
+    ));
+
+    mgr.exec_command("update low; low; rty; 2015-09-05 00:00; fgh; priority=urgent")
+        .unwrap();
+    assert_eq!(
+        names(&mgr, "select * where priority=urgent"),
+        ["low", "urgent"]
+    );
+}
+
+#[test]
+fn tags() {
+    let mut mgr = TaskManager::new();
+    assert_eq!(
+        mgr.exec_command("add report; q3; 2015-09-05 00:00; work; tags=urgent, finance")
+            .unwrap(),
+        "Task added: #1, due 2015-09-05 00:00, tags finance, urgent"
+    );
+    mgr.exec_command("add call; bank; 2015-09-06 00:00; home; tags=finance,phone");
+    mgr.exec_command("add walk; dog; 2015-09-07 00:00; home");
+
+    let names = |mgr: &TaskManager, query: &str| -> Vec<String> {
+        mgr.select(query)
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect()
+    };
+    assert_eq!(
+        names(&mgr, "select * where tags has finance"),
+        ["report", "call"]
+    );
+    assert_eq!(names(&mgr, "select * where tags has urgent"), ["report"]);
+    assert_eq!(
+        names(&mgr, "select * where tags any (phone, urgent)"),
+        ["report", "call"]
+    );
+    assert_eq!(
+        names(&mgr, "select * where tags all (finance, phone)"),
+        ["call"]
+    );
+    assert_eq!(
+        names(
+            &mgr,
+            "select * where not tags any (finance) and category=home"
+        ),
+        ["walk"]
+    );
+    assert_eq!(
+        mgr.select_rows("select name, tags where tags has phone")
+            .unwrap()[0]
+            .to_string(),
+        "name: call, tags: finance, phone"
+    );
+
+    mgr.exec_command(
+        "update call; call; bank; 2015-09-06 00:00; home; tags-=finance; tags+=urgent",
+    )
+    .unwrap();
+    assert_eq!(
+        names(&mgr, "select * where tags has urgent"),
+        ["report", "call"]
+    );
+    assert_eq!(names(&mgr, "select * where tags has finance"), ["report"]);
+    mgr.exec_command("update call; call; bank; 2015-09-06 00:00; home; tags=")
+        .unwrap();
+    assert!(mgr.find("call").unwrap().tags.is_empty());
+
+    assert!(matches!(
+        mgr.select("select * where tags=finance"),
+        Err(TaskMgrError::WrongQueryPropExpr(QueryError {
+            kind: ExpressionError::UnsupportedOpError(LeftVar::Tags),
+            ..
+        }))
+    ));
+    assert!(matches!(
+        mgr.select("select * where name has finance"),
+        Err(TaskMgrError::WrongQueryPropExpr(QueryError {
+            kind: ExpressionError::UnsupportedOpError(LeftVar::Name),
+            ..
+        }))
+    ));
+    assert!(matches!(
+        mgr.select("select * where tags any (a, b"),
+        Err(TaskMgrError::WrongQueryPropExpr(QueryError {
+            kind: ExpressionError::UnbalancedParensError,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn subtasks() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add release; v2; 2015-09-10 00:00; work");
+    assert_eq!(
+        mgr.exec_command("add docs; readme; 2015-09-08 00:00; work; parent=release")
+            .unwrap(),
+        "Task added: #2, due 2015-09-08 00:00, subtask of #1"
+    );
+    mgr.exec_command("add tests; unit; 2015-09-07 00:00; work; parent=#1");
+    mgr.exec_command("add examples; cli; 2015-09-06 00:00; work; parent=docs");
+    mgr.exec_command("add walk; dog; 2015-09-07 00:00; home");
+    mgr.exec_command("done examples");
+
+    assert_eq!(
+        mgr.exec_command("tree").unwrap(),
+        [
+            "#1 release [todo], due 2015-09-10 00:00, 0/2 subtasks done",
+            "  #2 docs [todo], due 2015-09-08 00:00, 1/1 subtasks done",
+            "    #4 examples [done], due 2015-09-06 00:00",
+            "  #3 tests [todo], due 2015-09-07 00:00",
+            "#5 walk [todo], due 2015-09-07 00:00",
+        ]
+        .join("\n")
+    );
+    mgr.exec_command("cancel tests");
+    assert_eq!(
+        mgr.exec_command("  tree\tdocs ").unwrap(),
+        [
+            "#2 docs [todo], due 2015-09-08 00:00, 1/1 subtasks done",
+            "  #4 examples [done], due 2015-09-06 00:00",
+        ]
+        .join("\n")
+    );
+    assert_eq!(mgr.tree().unwrap().progress(1), Some((0, 1)));
+
+    assert!(matches!(
+        mgr.exec_command("add misc; x; 2015-09-07 00:00; work; parent=#42"),
+        Err(TaskMgrError::TaskNotFound(task_ref)) if task_ref == "#42"
+    ));
+    assert!(matches!(
+        mgr.exec_command("update release; release; v2; 2015-09-10 00:00; work; parent=examples"),
+        Err(TaskMgrError::SubtaskCycleError(1, 4))
+    ));
+    assert!(matches!(
+        mgr.exec_command("update docs; docs; readme; 2015-09-08 00:00; work; parent=#2"),
+        Err(TaskMgrError::SubtaskCycleError(2, 2))
+    ));
+    assert_eq!(
+        TaskMgrError::SubtaskCycleError(1, 4).to_string(),
+        "#4 is a subtask of #1, it cannot be its parent"
+    );
+    mgr.exec_command("update walk; walk; dog; 2015-09-07 00:00; home; parent=release")
+        .unwrap();
+    mgr.exec_command("update walk; walk; dog; 2015-09-07 00:00; home; parent=none")
+        .unwrap();
+    assert_eq!(mgr.find("walk").unwrap().parent, None);
+
+    // docs is open, tests is cancelled
+    let mut strict =
+        TaskManager::with_storage(MemoryStorage::from_tasks(mgr.get_tasks().unwrap(), 6))
+            .with_strict_subtasks(true);
+    assert!(matches!(
+        strict.exec_command("done release"),
+        Err(TaskMgrError::OpenSubtasksError(task_ref, 1)) if task_ref == "release"
+    ));
+    strict.exec_command("done docs").unwrap();
+    strict.exec_command("done release").unwrap();
+    mgr.exec_command("done release").unwrap();
+
+    assert_eq!(
+        mgr.exec_command("delete docs").unwrap(),
+        "Task deleted, 1 subtask(s) moved up"
+    );
+    assert_eq!(mgr.find("examples").unwrap().parent, Some(1));
+    assert!(matches!(
+        mgr.exec_command("delete release; everything"),
+        Err(TaskMgrError::WrongQuery(_))
+    ));
+    assert_eq!(
+        mgr.exec_command("delete release; cascade").unwrap(),
+        "Task deleted along with 2 subtask(s)"
+    );
+    assert_eq!(
+        mgr.exec_command("tree").unwrap(),
+        "#5 walk [todo], due 2015-09-07 00:00"
+    );
+}
+
+#[test]
+fn dependencies() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add design; api; 2015-09-05 00:00; work");
+    mgr.exec_command("add build; api; 2015-09-06 00:00; work; blocked_by=design");
+    assert_eq!(
+        mgr.exec_command("add ship; api; 2015-09-07 00:00; work; blocked_by=build, #1")
+            .unwrap(),
+        "Task added: #3, due 2015-09-07 00:00, after #1, #2"
+    );
+    mgr.exec_command("add docs; api; 2015-09-08 00:00; work; priority=high");
+    mgr.exec_command("add walk; dog; 2015-09-07 00:00; home; blocked_by=docs");
+
+    let names = |mgr: &TaskManager, query: &str| -> Vec<String> {
+        mgr.select(query)
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect()
+    };
+    assert_eq!(
+        names(&mgr, "select * where is_blocked=true"),
+        ["build", "ship", "walk"]
+    );
+    assert_eq!(
+        names(&mgr, "select * where is_blocked=yes or is_blocked=1"),
+        ["build", "ship", "walk"]
+    );
+    assert_eq!(
+        names(&mgr, "select * where is_blocked=no and category=work"),
+        ["docs", "design"]
+    );
+    assert_eq!(
+        mgr.select_rows("select name, is_blocked where name=build")
+            .unwrap()[0]
+            .to_string(),
+        "name: build, is_blocked: true"
+    );
+    assert!(matches!(
+        mgr.select("select * where is_blocked=maybe"),
+        Err(TaskMgrError::WrongQueryPropExpr(QueryError {
+            kind: ExpressionError::ValueTypeError(LeftVar::IsBlocked, _),
+            ..
+        }))
+    ));
+
+    assert_eq!(
+        mgr.exec_command("order").unwrap(),
+        [
+            "Execution order:",
+            "1. #4 docs, due 2015-09-08 00:00",
+            "2. #1 design, due 2015-09-05 00:00",
+            "3. #2 build, due 2015-09-06 00:00, after #1",
+            "4. #3 ship, due 2015-09-07 00:00, after #1, #2",
+            "5. #5 walk, due 2015-09-07 00:00, after #4",
+        ]
+        .join("\n")
+    );
+
+    assert!(matches!(
+        mgr.exec_command("start build"),
+        Err(TaskMgrError::WaitingOnError(task_ref, blockers))
+            if task_ref == "build" && blockers == [1]
+    ));
+    assert!(matches!(
+        mgr.exec_command("update design; design; api; 2015-09-05 00:00; work; blocked_by+=ship"),
+        Err(TaskMgrError::DependencyCycleError(cycle)) if cycle == [1, 3, 1]
+    ));
+    assert!(matches!(
+        mgr.exec_command("update design; design; api; 2015-09-05 00:00; work; blocked_by=walk, #1"),
+        Err(TaskMgrError::DependencyCycleError(cycle)) if cycle == [1, 1]
+    ));
+    assert_eq!(
+        TaskMgrError::DependencyCycleError(vec![1, 3, 2, 1]).to_string(),
+        "the tasks would wait on each other: #1 -> #3 -> #2 -> #1"
+    );
+    assert!(matches!(
+        mgr.exec_command("add misc; x; 2015-09-07 00:00; work; blocked_by=#42"),
+        Err(TaskMgrError::TaskNotFound(task_ref)) if task_ref == "#42"
+    ));
+
+    mgr.exec_command("done design").unwrap();
+    mgr.exec_command("start build").unwrap();
+    mgr.exec_command("cancel docs").unwrap();
+    assert_eq!(names(&mgr, "select * where is_blocked=true"), ["ship"]);
+    assert!(!mgr.find("walk").unwrap().is_blocked);
+
+    mgr.exec_command("update ship; ship; api; 2015-09-07 00:00; work; blocked_by-=build")
+        .unwrap();
+    assert_eq!(mgr.find("ship").unwrap().blocked_by, [1].into());
+    mgr.exec_command("update ship; ship; api; 2015-09-07 00:00; work; blocked_by+=build")
+        .unwrap();
+    mgr.exec_command("delete build").unwrap();
+    assert_eq!(mgr.find("ship").unwrap().blocked_by, [1].into());
+}
+
+#[test]
+fn export_graphs() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add design; api; 2015-09-05 00:00; work");
+    mgr.exec_command("add build; api; 2015-09-06 12:00; work; blocked_by=design; priority=urgent");
+    mgr.exec_command("add \"readme\"; api; 2015-09-07 00:00; docs; parent=build");
+    mgr.exec_command("add walk; dog; 2015-09-07 00:00; home");
+    mgr.exec_command("done design");
+    mgr.exec_command("start build");
+
+    assert_eq!(
+        mgr.exec_command("export dot where category!=home").unwrap(),
+        [
+            "digraph tasks {",
+            "    rankdir=LR;",
+            "    node [shape=box, style=filled];",
+            "    subgraph cluster_0 {",
+            "        label=\"work\";",
+            "        t2 [label=\"#2 build\", fillcolor=\"#cfe2ff\"];",
+            "        t1 [label=\"#1 design\", fillcolor=\"#d1e7dd\"];",
+            "    }",
+            "    subgraph cluster_1 {",
+            "        label=\"docs\";",
+            "        t3 [label=\"#3 \\\"readme\\\"\", fillcolor=\"#ffffff\"];",
+            "    }",
+            "    t1 -> t2;",
+            "    t2 -> t3 [style=dashed, arrowhead=none];",
+            "}",
+        ]
+        .join("\n")
+    );
+    assert_eq!(
+        mgr.exec_command("export mermaid where category!=home order by date")
+            .unwrap(),
+        [
+            "flowchart LR",
+            "    subgraph c0 [\"work\"]",
+            "        t1[\"#35;1 design\"]",
+            "        t2[\"#35;2 build\"]",
+            "    end",
+            "    subgraph c1 [\"docs\"]",
+            "        t3[\"#35;3 #quot;readme#quot;\"]",
+            "    end",
+            "    t1 --> t2",
+            "    t2 -.- t3",
+            "    classDef todo fill:#ffffff",
+            "    class t3 todo",
+            "    classDef in_progress fill:#cfe2ff",
+            "    class t2 in_progress",
+            "    classDef done fill:#d1e7dd",
+            "    class t1 done",
+        ]
+        .join("\n")
+    );
+    assert_eq!(
+        mgr.exec_command("export gantt where category=work order by date")
+            .unwrap(),
+        [
+            "gantt",
+            "    dateFormat YYYY-MM-DD HH:mm",
+            "    section work",
+            "    design :milestone, done, t1, 2015-09-05 00:00, 0d",
+            "    build :active, crit, t2, after t1, 36h",
+        ]
+        .join("\n")
+    );
+    // the awaited task is filtered out, so build has nothing to wait on
+    assert_eq!(
+        mgr.exec_command("export gantt where name=build")
+            .unwrap()
+            .lines()
+            .last(),
+        Some("    build :milestone, active, crit, t2, 2015-09-06 12:00, 0d")
+    );
+    // due before the task it waits on, so it can't run past it
+    mgr.exec_command("update design; design; api; 2015-09-08 00:00; work");
+    assert_eq!(
+        mgr.exec_command(" export  gantt where category=work")
+            .unwrap()
+            .lines()
+            .find(|line| line.starts_with("    build")),
+        Some("    build :active, crit, t2, after t1, 0d")
+    );
+
+    assert!(matches!(
+        mgr.exec_command("export svg"),
+        Err(TaskMgrError::WrongQuery(_))
+    ));
+    assert!(matches!(
+        mgr.exec_command("export dot where category=nowhere"),
+        Err(TaskMgrError::TaskNotFound(_))
+    ));
+}
+
+#[test]
+fn undo_redo() {
+    let mut mgr = TaskManager::new().with_history_limit(3);
+    assert!(matches!(
+        mgr.exec_command("undo"),
+        Err(TaskMgrError::NothingToUndo)
+    ));
+    assert_eq!(
+        mgr.exec_command("history").unwrap(),
+        "Nothing to undo or redo"
+    );
+
+    mgr.exec_command("add design; api; 2015-09-05 00:00; work");
+    mgr.exec_command("add build; api; 2015-09-06 00:00; work; parent=design");
+    mgr.exec_command("update design; design; rest api; 2015-09-05 00:00; work")
+        .unwrap();
+    mgr.exec_command("delete design; cascade").unwrap();
+    // nothing changes, so there is nothing to undo
+    mgr.exec_command("select *");
+    mgr.exec_command("delete design");
+    assert!(mgr.get_tasks().unwrap().is_empty());
+
+    assert_eq!(
+        mgr.exec_command("history").unwrap(),
+        [
+            "Undo would revert:",
+            "1. delete design; cascade: deleted #1 design, deleted #2 build",
+            "2. update design; design; rest api; 2015-09-05 00:00; work: changed #1 design",
+            "3. add build; api; 2015-09-06 00:00; work; parent=design: added #2 build",
+        ]
+        .join("\n")
+    );
+
+    assert_eq!(
+        mgr.exec_command("undo").unwrap(),
+        "Undone delete design; cascade: deleted #1 design, deleted #2 build"
+    );
+    assert_eq!(mgr.find("#2").unwrap().parent, Some(1));
+    assert_eq!(mgr.find("#1").unwrap().description, "rest api");
+    mgr.exec_command("undo").unwrap();
+    assert_eq!(mgr.find("#1").unwrap().description, "api");
+    assert_eq!(
+        mgr.get_tasks()
+            .unwrap()
+            .into_iter()
+            .map(|task| task.id)
+            .collect::<Vec<_>>(),
+        [1, 2]
+    );
+
+    mgr.exec_command("undo").unwrap();
+    // the first add fell out of the history
+    assert!(matches!(mgr.undo(), Err(TaskMgrError::NothingToUndo)));
+    assert_eq!(mgr.get_tasks().unwrap().len(), 1);
+
+    assert_eq!(
+        mgr.exec_command("redo").unwrap(),
+        "Redone add build; api; 2015-09-06 00:00; work; parent=design: added #2 build"
+    );
+    assert_eq!(
+        mgr.exec_command("history").unwrap(),
+        [
+            "Undo would revert:",
+            "1. add build; api; 2015-09-06 00:00; work; parent=design: added #2 build",
+            "Redo would repeat:",
+            "1. update design; design; rest api; 2015-09-05 00:00; work: changed #1 design",
+            "2. delete design; cascade: deleted #1 design, deleted #2 build",
+        ]
+        .join("\n")
+    );
+
+    // a new command drops what could be redone
+    mgr.exec_command("add walk; dog; 2015-09-07 00:00; home; repeat=daily");
+    assert!(matches!(
+        mgr.exec_command("redo"),
+        Err(TaskMgrError::NothingToRedo)
+    ));
+
+    mgr.exec_command("done walk").unwrap();
+    assert_eq!(mgr.get_tasks().unwrap().len(), 4);
+    assert_eq!(
+        mgr.exec_command("undo").unwrap(),
+        "Undone done walk: changed #3 walk, added #4 walk"
+    );
+    let walk = mgr.find("walk").unwrap();
+    assert_eq!(walk.status, Status::Todo);
+    assert!(walk.recurrence.is_some());
+}
+
+#[test]
+fn repl_update_sets_priority() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh");
+
+    let fields = ["qwe", "rty", "2015-09-05 00:00", "fgh"];
+    mgr.exec_command(&compose_update("update qwe; priority=urgent", fields))
+        .unwrap();
+    assert_eq!(mgr.find("qwe").unwrap().priority, Priority::Urgent);
+}
+
+#[test]
+fn repl_update_edits_tags() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add qwe; rty; 2015-09-05 00:00; fgh; tags=home, q3");
+
+    let fields = ["qwe", "rty", "2015-09-05 00:00", "fgh"];
+    mgr.exec_command(&compose_update(
+        "update qwe; tags+=finance; tags-=home",
+        fields,
+    ))
+    .unwrap();
+    let tags: Vec<String> = mgr.find("qwe").unwrap().tags.into_iter().collect();
+    assert_eq!(tags, ["finance", "q3"]);
+}
+
+#[test]
+fn repl_update_detaches_subtask() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add release; ; 2015-09-05 00:00; work");
+    mgr.exec_command("add tests; ; 2015-09-04 00:00; work; parent=release");
+
+    assert_eq!(mgr.find("tests").unwrap().parent, Some(1));
+
+    let fields = ["tests", "", "2015-09-04 00:00", "work"];
+    mgr.exec_command(&compose_update("update tests; parent=none", fields))
+        .unwrap();
+    assert_eq!(mgr.find("tests").unwrap().parent, None);
+}
+
+#[test]
+fn repl_update_sets_blockers() {
+    let mut mgr = TaskManager::new();
+    mgr.exec_command("add build; ; 2015-09-04 00:00; work");
+    mgr.exec_command("add deploy; ; 2015-09-05 00:00; work");
+
+    let fields = ["deploy", "", "2015-09-05 00:00", "work"];
+    mgr.exec_command(&compose_update("update deploy; blocked_by=build", fields))
+        .unwrap();
+    let deploy = mgr.find("deploy").unwrap();
+    assert_eq!(deploy.blocked_by.into_iter().collect::<Vec<_>>(), [1]);
+}
@@ -1,4 +1,5 @@
-use crate::back::task_model::Task;
+use crate::back::errors::TaskError;
+use crate::back::task_model::{Status, Task};
 
 #[test]
 fn task_created() {
@@ -28,6 +29,46 @@ fn task_updated() {
 #[test]
 fn task_marked_done() {
     let mut task = Task::new("qwe; rty; 2015-09-05 00:00; fgh").unwrap();
-    task.check_done();
-    assert!(task.done);
+    task.transition(Status::Done).unwrap();
+    assert_eq!(task.status, Status::Done);
+}
+
+#[test]
+fn task_status_transitions() {
+    let mut task = Task::new("qwe; rty; 2015-09-05 00:00; fgh").unwrap();
+    assert_eq!(task.status, Status::Todo);
+
+    task.transition(Status::InProgress).unwrap();
+    task.transition(Status::Blocked).unwrap();
+    assert!(matches!(
+        task.transition(Status::Done),
+        Err(TaskError::IllegalTransitionError(
+            Status::Blocked,
+            Status::Done
+        ))
+    ));
+    task.transition(Status::InProgress).unwrap();
+    task.transition(Status::Done).unwrap();
+
+    // done twice is an error rather than reopening the task
+    assert!(task.transition(Status::Done).is_err());
+    assert!(task.transition(Status::InProgress).is_err());
+    task.transition(Status::Todo).unwrap();
+    task.transition(Status::Cancelled).unwrap();
+    assert_eq!(task.status, Status::Cancelled);
+}
+
+#[test]
+fn task_reads_legacy_done_flag() {
+    let json = r#"{"id": 1, "name": "qwe", "description": "rty", "date": "2015-09-05T00:00:00",
+        "category": "fgh", "done": true}"#;
+    let task: Task = serde_json::from_str(json).unwrap();
+    assert_eq!(task.status, Status::Done);
+
+    let json = json.replace("\"done\": true", "\"status\": \"in-progress\"");
+    let task: Task = serde_json::from_str(&json).unwrap();
+    assert_eq!(task.status, Status::InProgress);
+
+    let serialized = serde_json::to_string(&task).unwrap();
+    assert!(serialized.contains("\"status\":\"in-progress\""));
 }
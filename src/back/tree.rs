@@ -0,0 +1,106 @@
+#![allow(dead_code)]
+use super::task_model::Status;
+use super::Task;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Tasks arranged by their [Task::parent], siblings in insertion order.
+///
+/// Tasks whose parent is not among the given ones are treated as top level.
+#[derive(Debug, Clone)]
+pub struct TaskTree {
+    tasks: Vec<Task>,
+    /// Positions in `tasks` of the subtasks of each task, [None] holding the top level ones
+    children: BTreeMap<Option<u64>, Vec<usize>>,
+}
+
+impl TaskTree {
+    pub fn new(tasks: Vec<Task>) -> Self {
+        let ids: BTreeSet<u64> = tasks.iter().map(|task| task.id).collect();
+        let mut children: BTreeMap<Option<u64>, Vec<usize>> = BTreeMap::new();
+        for (position, task) in tasks.iter().enumerate() {
+            let parent = task.parent.filter(|parent| ids.contains(parent));
+            children.entry(parent).or_default().push(position);
+        }
+
+        Self { tasks, children }
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Task> {
+        self.tasks.iter().find(|task| task.id == id)
+    }
+
+    /// Direct subtasks of the task, the top level tasks for [None]
+    pub fn children(&self, id: Option<u64>) -> Vec<&Task> {
+        self.children
+            .get(&id)
+            .map(|positions| positions.iter().map(|&i| &self.tasks[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Subtasks of the task at any depth, each one before its own subtasks
+    pub fn descendants(&self, id: u64) -> Vec<&Task> {
+        self.walk(Some(id))
+            .into_iter()
+            .map(|(_, task)| task)
+            .collect()
+    }
+
+    /// Ancestors of the task, from its parent up to the top level
+    pub fn ancestors(&self, id: u64) -> Vec<&Task> {
+        let mut ancestors: Vec<&Task> = Vec::new();
+        let mut current = self.get(id);
+        while let Some(parent) = current.and_then(|task| task.parent) {
+            // a broken file could hold a loop, stop once it comes around
+            if parent == id || ancestors.iter().any(|task| task.id == parent) {
+                break;
+            }
+            current = self.get(parent);
+            ancestors.extend(current);
+        }
+        ancestors
+    }
+
+    /// How many of the direct subtasks are done out of how many there are,
+    /// cancelled ones are not counted. [None] when the task has no subtasks to count.
+    pub fn progress(&self, id: u64) -> Option<(usize, usize)> {
+        let subtasks: Vec<&Task> = self
+            .children(Some(id))
+            .into_iter()
+            .filter(|task| task.status != Status::Cancelled)
+            .collect();
+        if subtasks.is_empty() {
+            return None;
+        }
+
+        let done = subtasks
+            .iter()
+            .filter(|task| task.status == Status::Done)
+            .count();
+        Some((done, subtasks.len()))
+    }
+
+    /// The subtasks below `root` (the whole tree for [None]) depth first,
+    /// along with how deep they are, `0` for the direct subtasks of `root`
+    pub fn walk(&self, root: Option<u64>) -> Vec<(usize, &Task)> {
+        let mut walked = Vec::new();
+        let mut seen = BTreeSet::new();
+        self.walk_into(root, 0, &mut seen, &mut walked);
+        walked
+    }
+
+    fn walk_into<'a>(
+        &'a self,
+        parent: Option<u64>,
+        depth: usize,
+        seen: &mut BTreeSet<u64>,
+        walked: &mut Vec<(usize, &'a Task)>,
+    ) {
+        for task in self.children(parent) {
+            if !seen.insert(task.id) {
+                continue;
+            }
+            walked.push((depth, task));
+            self.walk_into(Some(task.id), depth + 1, seen, walked);
+        }
+    }
+}
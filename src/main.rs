@@ -1,39 +1,214 @@
 mod back;
 use back::*;
-use errors::TaskMgrError;
+use chrono::NaiveTime;
+use chrono_tz::Tz;
+use dates::DateParser;
+use errors::{TaskError, TaskMgrError};
+use natural::Locale;
+use std::env;
+use std::error::Error;
 use std::io::stdin;
+use std::path::PathBuf;
+use std::process;
+use storage::{JournalStorage, JsonFileStorage, MemoryStorage, SqliteStorage, Storage};
 use task_manager::TaskManager;
 
+/// Environment variable used when no `--file` flag is given
+const TASKS_FILE_ENV: &str = "TASKS_FILE";
+/// Environment variable used when no `--journal` flag is given
+const TASKS_JOURNAL_ENV: &str = "TASKS_JOURNAL";
+/// Environment variable used when no `--sqlite` flag is given
+const TASKS_DB_ENV: &str = "TASKS_DB";
+/// Environment variable used when no `--locale` flag is given
+const TASKS_LOCALE_ENV: &str = "TASKS_LOCALE";
+/// Environment variable used when no `--timezone` flag is given
+const TASKS_TZ_ENV: &str = "TASKS_TZ";
+/// Environment variable used when no `--date-formats` flag is given
+const TASKS_DATE_FORMATS_ENV: &str = "TASKS_DATE_FORMATS";
+/// Environment variable used when no `--default-time` flag is given
+const TASKS_DEFAULT_TIME_ENV: &str = "TASKS_DEFAULT_TIME";
+/// Environment variable used when no `--display-format` flag is given
+const TASKS_DISPLAY_FORMAT_ENV: &str = "TASKS_DISPLAY_FORMAT";
+/// Environment variable used when no `--strict-subtasks` flag is given
+const TASKS_STRICT_SUBTASKS_ENV: &str = "TASKS_STRICT_SUBTASKS";
+
 fn main() {
-    let mut task_manager = TaskManager::new();
+    let storage = open_storage().unwrap_or_else(|e| {
+        eprintln!("Failed to load tasks: {}", report(&e));
+        process::exit(1);
+    });
+    let dates = open_dates().unwrap_or_else(|e| {
+        eprintln!("Failed to configure dates: {}", report(&e));
+        process::exit(1);
+    });
+    let mut task_manager = TaskManager::with_storage(storage)
+        .with_dates(dates)
+        .with_strict_subtasks(flag_set("--strict-subtasks", TASKS_STRICT_SUBTASKS_ENV));
     loop {
         println!("\n\tBlazingly Fast Task Manager (glorified to-do list) v.00.00.032.1a\n");
         println!("- 'add %name%, %description%, %date%, %status%' creates a new task.");
-        println!("- `update %task_name%` updates a task with new values.");
-        println!("- 'delete %task_name%' removes a task.");
-        println!("- `select *` lists all of the tasks.");
+        println!("-\t- Add 'repeat=every 2 weeks on mon,fri until 2024-12-31 count 10' to make it recurring.");
+        println!("-\t- Add 'priority=low|normal|high|urgent' to set its importance, urgent tasks are listed first.");
+        println!("-\t- Add 'tags=work, q3' to label it, 'tags+=x' and 'tags-=x' change the labels on update.");
+        println!("-\t- Add 'parent=%task_name%' to make it a subtask, 'parent=none' on update moves it back to the top.");
+        println!("-\t- Add 'blocked_by=%task_name%, #3' to make it wait on other tasks, it can't be started or done before them.");
+        println!("- `update %task_name%` updates a task with new values, options go after the name: 'update %task_name%; repeat=weekly'.");
+        println!("- 'delete %task_name%' removes a task, its subtasks move up, 'delete %task_name%; cascade' removes them too.");
+        println!("- 'order' lists the open tasks in an order that has every task after the ones it waits on.");
+        println!("- 'export dot|mermaid|gantt [where ...]' prints the tasks and their links as a Graphviz or Mermaid graph.");
+        println!("- 'tree' shows the tasks under their parents, 'tree %task_name%' only the ones under that task.");
+        println!("- 'start', 'block', 'done', 'cancel' and 'reopen %task_name%' move a task through todo, in-progress, blocked, done and cancelled.");
+        println!("-\t- Tasks can also be addressed by their id, e.g. 'delete #3'.");
+        println!("- 'undo' takes back the last change, 'redo' brings it back, 'history' lists what they would do.");
+        println!("- `select *` lists all of the tasks, `select name, date` only shows the listed fields.");
         println!("- `select * where` shows tasks filtered by the specified args.\n-\t- For example: 'select * where name=task and category=misc and status=true'");
-        println!("-\t- Available args: 'name, description, date, category, status'\n");
+        println!("-\t- Conditions combine with 'and', 'or', 'not' and parentheses: 'select * where not status=true and (category=work or category=home)'");
+        println!("-\t- Results can be sorted and paged: 'select * where category=work order by date desc, name limit 10 offset 20'");
+        println!("-\t- Counts: 'select count(*) where status=false group by category', dates group by 'date day|week|month'");
+        println!(
+            "-\t- Available args: 'name, description, date, category, status, priority, tags, is_blocked'"
+        );
+        println!("-\t- Tags are matched with 'tags has work', 'tags any (work, home)' and 'tags all (work, q3)'");
+        println!("-\t- Status takes a state or a boolean for done: 'status=in-progress', 'status<done', 'status=true'");
+        println!(
+            "- Dates can be relative: 'today', 'tomorrow 09:00', '+3d', '-2w', 'start_of_week +1w'"
+        );
+        println!("-\t- or plain words: 'next friday 17:00', 'in 2 hours' ('через 2 часа' with --locale ru)");
+        println!("-\t- Absolute dates also take '2024-05-01', '01.05.2024 09:00' and '2024-05-01T09:00:00'");
+        println!("-\t- Dates are in the --timezone zone unless one is named: '2024-05-01 09:00 Europe/Berlin'\n");
 
         let input = manage_input(&mut task_manager);
         match input {
             Ok(ok) => println!("{}", ok),
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => println!("Error: {}", report(&e)),
         }
         println!("Enter to continue...");
         stdin().read_line(&mut String::new()).unwrap();
     }
 }
 
-fn match_input(input: &String, task_manager: &mut TaskManager) -> Result<String, TaskMgrError> {
+/// Picks the storage backend from the command line:
+/// - `--sqlite <path>` (or `TASKS_DB`) keeps the tasks in an SQLite database
+/// - `--journal <path>` (or `TASKS_JOURNAL`) keeps a crash-safe journal next to the snapshot at `path`
+/// - `--file <path>` (or `TASKS_FILE`) rewrites a single JSON document on every change
+///
+/// Without either the tasks only live in memory.
+fn open_storage() -> Result<Box<dyn Storage>, TaskMgrError> {
+    if let Some(path) = flag_value("--sqlite", TASKS_DB_ENV) {
+        return Ok(Box::new(SqliteStorage::open(path)?));
+    }
+    if let Some(path) = flag_value("--journal", TASKS_JOURNAL_ENV) {
+        return Ok(Box::new(JournalStorage::open(path)?));
+    }
+    if let Some(path) = flag_value("--file", TASKS_FILE_ENV) {
+        return Ok(Box::new(JsonFileStorage::open(path)?));
+    }
+
+    Ok(Box::new(MemoryStorage::new()))
+}
+
+/// Configures how dates are read and printed:
+/// - `--date-formats <format;format>` (or `TASKS_DATE_FORMATS`) replaces the accepted input formats
+/// - `--default-time <HH:MM>` (or `TASKS_DEFAULT_TIME`) is the time given to dates entered without one
+/// - `--display-format <format>` (or `TASKS_DISPLAY_FORMAT`) is how dates are printed back
+/// - `--timezone <IANA name>` (or `TASKS_TZ`, then `TZ`) is the zone dates are entered and shown in,
+///   UTC by default
+fn open_dates() -> Result<DateParser, TaskMgrError> {
+    let mut dates = DateParser::default().with_locale(open_locale());
+
+    if let Some(zone) = flag_value("--timezone", TASKS_TZ_ENV) {
+        let zone = zone.to_string_lossy();
+        let zone = zone.parse::<Tz>().map_err(|_| {
+            TaskMgrError::GeneralTaskError(TaskError::TaskZoneParseError(zone.to_string()))
+        })?;
+        dates = dates.with_zone(zone);
+    } else if let Some(zone) = env::var("TZ").ok().and_then(|zone| zone.parse::<Tz>().ok()) {
+        dates = dates.with_zone(zone);
+    }
+
+    if let Some(formats) = flag_value("--date-formats", TASKS_DATE_FORMATS_ENV) {
+        let formats = formats.to_string_lossy();
+        dates = dates
+            .with_input_formats(formats.split(';').map(str::trim))
+            .map_err(TaskMgrError::GeneralTaskError)?;
+    }
+    if let Some(time) = flag_value("--default-time", TASKS_DEFAULT_TIME_ENV) {
+        let time = time.to_string_lossy();
+        let time = NaiveTime::parse_from_str(&time, "%H:%M").map_err(|_| {
+            TaskMgrError::GeneralTaskError(TaskError::TaskDateParseError(time.to_string()))
+        })?;
+        dates = dates.with_default_time(time);
+    }
+    if let Some(format) = flag_value("--display-format", TASKS_DISPLAY_FORMAT_ENV) {
+        dates = dates
+            .with_display_format(&format.to_string_lossy())
+            .map_err(TaskMgrError::GeneralTaskError)?;
+    }
+
+    Ok(dates)
+}
+
+/// Language of the natural-language dates, `--locale en|ru` (or `TASKS_LOCALE`), English by default
+fn open_locale() -> Locale {
+    let Some(code) = flag_value("--locale", TASKS_LOCALE_ENV) else {
+        return Locale::default();
+    };
+
+    Locale::from_code(&code.to_string_lossy()).unwrap_or_else(|| {
+        eprintln!("Unknown locale {:?}, falling back to English", code);
+        Locale::default()
+    })
+}
+
+/// Resolves `flag <value>` (or `flag=<value>`) from the arguments,
+/// falling back to the `env` environment variable.
+fn flag_value(flag: &str, env: &str) -> Option<PathBuf> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(value) = arg
+            .strip_prefix(flag)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(PathBuf::from(value));
+        }
+    }
+
+    env::var_os(env).map(PathBuf::from)
+}
+
+/// Whether `flag` is among the arguments or the `env` environment variable is set
+fn flag_set(flag: &str, env: &str) -> bool {
+    env::args().skip(1).any(|arg| arg == flag) || env::var_os(env).is_some()
+}
+
+/// The error followed by the ones that caused it:
+/// `could not add the task: '2022-09-32' is not a date`
+fn report(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
+fn match_input(
+    input: &str,
+    task_manager: &mut TaskManager<Box<dyn Storage>>,
+) -> Result<String, TaskMgrError> {
     task_manager.exec_command(input)
 }
 
-fn manage_input(task_manager: &mut TaskManager) -> Result<String, TaskMgrError> {
+fn manage_input(task_manager: &mut TaskManager<Box<dyn Storage>>) -> Result<String, TaskMgrError> {
     let mut input = String::new();
     stdin().read_line(&mut input).expect("Input error.");
 
-    let command_is_update = expression::command_equals(&input, "update").unwrap();
+    // a blank line is not an update, exec_command reports it
+    let command_is_update = expression::command_equals(&input, "update").unwrap_or(false);
     if command_is_update {
         input = compose_update_query(&input)
     }
@@ -41,7 +216,7 @@ fn manage_input(task_manager: &mut TaskManager) -> Result<String, TaskMgrError>
     match_input(&input, task_manager)
 }
 
-fn compose_update_query(update_task_name: &String) -> String {
+fn compose_update_query(update_task_name: &str) -> String {
     let (mut new_name, mut desc, mut date, mut cat) =
         (String::new(), String::new(), String::new(), String::new());
 
@@ -57,12 +232,15 @@ fn compose_update_query(update_task_name: &String) -> String {
     println!("Enter new task category: ");
     stdin().read_line(&mut cat).unwrap();
 
-    format!(
-        "{}; {}; {}; {}; {}",
-        update_task_name,
-        new_name.trim(),
-        desc.trim(),
-        date.trim(),
-        cat.trim()
-    )
+    compose_update(update_task_name, [&new_name, &desc, &date, &cat])
+}
+
+/// Puts the prompted fields between the task and the `key=value` options typed after it:
+/// `update qwe; repeat=weekly` becomes `update qwe; name; description; date; category; repeat=weekly`
+fn compose_update(update_task_name: &str, fields: [&str; 4]) -> String {
+    let mut typed = update_task_name.split(';').map(str::trim);
+    let mut composed: Vec<&str> = typed.next().into_iter().collect();
+    composed.extend(fields.map(str::trim));
+    composed.extend(typed.filter(|option| !option.is_empty()));
+    composed.join("; ")
 }